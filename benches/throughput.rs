@@ -0,0 +1,110 @@
+// Measure how fast mailparse goes through a large uncompressed log file, with
+// and without memory-mapping it
+//
+// The log file is generated in the temporary directory on the first run, its
+// size can be set in bytes with MAILPARSE_BENCH_SIZE (2GiB by default).
+
+use std::{
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Instant,
+};
+
+const DEFAULT_SIZE: u64 = 2 << 30;
+
+// A tiny deterministic pseudo-random generator, to get varied but
+// reproducible logs
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
+
+fn generate(path: &Path, size: u64) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut out = BufWriter::new(std::fs::File::create(&tmp)?);
+    let mut rng = Lcg(42);
+    let mut written = 0;
+    let mut second = 0;
+    while written < size {
+        let id = format!("{:010X}", rng.next());
+        let next_id = format!("{:010X}", rng.next());
+        let pid = rng.next() % 30000;
+        let ip = format!("192.0.2.{}", rng.next() % 256);
+        let time = format!(
+            "Mar {:2} {:02}:{:02}:{:02}",
+            1 + second / 86400 % 28,
+            second / 3600 % 24,
+            second / 60 % 60,
+            second % 60
+        );
+        second += 1;
+        let lines = [
+            format!("{} mx postfix/smtpd[{}]: connect from client.example.org[{}]", time, pid, ip),
+            format!("{} mx postfix/smtpd[{}]: {}: client=client.example.org[{}]", time, pid, id, ip),
+            format!("{} mx postfix/cleanup[{}]: {}: message-id=<{}@example.org>", time, pid + 1, id, rng.next()),
+            format!("{} mx postfix/qmgr[1234]: {}: from=<sender{}@example.org>, size={}, nrcpt=1 (queue active)", time, id, rng.next() % 1000, rng.next() % 100000),
+            format!("{} mx postfix/smtpd[{}]: disconnect from client.example.org[{}] ehlo=1 mail=1 rcpt=1 data=1 quit=1 commands=5", time, pid, ip),
+            format!("{} mx postfix/smtp[{}]: {}: to=<user{}@example.net>, relay=relay.example.net[198.51.100.1]:25, delay=0.5, delays=0.1/0/0.2/0.2, dsn=2.0.0, status=sent (250 2.0.0 Ok: queued as {})", time, pid + 2, id, rng.next() % 1000, next_id),
+            format!("{} mx postfix/qmgr[1234]: {}: removed", time, id),
+        ];
+        for l in lines.iter() {
+            writeln!(out, "{}", l)?;
+            written += l.len() as u64 + 1;
+        }
+    }
+    out.flush()?;
+    drop(out);
+    std::fs::rename(tmp, path)
+}
+
+fn main() {
+    // `cargo test --benches` runs benchmarks with no argument, `cargo bench`
+    // with `--bench`
+    if !std::env::args().any(|a| a == "--bench") {
+        return;
+    }
+
+    let size = std::env::var("MAILPARSE_BENCH_SIZE")
+        .ok()
+        .map(|s| {
+            s.parse()
+                .expect("MAILPARSE_BENCH_SIZE must be a number of bytes")
+        })
+        .unwrap_or(DEFAULT_SIZE);
+    let path: PathBuf = std::env::temp_dir().join(format!("mailparse-bench-{}.log", size));
+    if !path.exists() {
+        eprintln!("generating {:?}…", path);
+        generate(&path, size).expect("failed generating the log file");
+    }
+    let size = std::fs::metadata(&path)
+        .expect("failed to stat the log file")
+        .len();
+
+    for (name, args) in [("read", &[][..]), ("mmap", &["--mmap"][..])].iter() {
+        let start = Instant::now();
+        let status = Command::new(env!("CARGO_BIN_EXE_mailparse"))
+            .args(args.iter())
+            .arg("not-in-the-logs@example.org")
+            .arg(&path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .expect("failed running mailparse");
+        let elapsed = start.elapsed();
+        assert!(status.success(), "mailparse failed");
+        println!(
+            "{}: {:.2}s, {:.1} MiB/s",
+            name,
+            elapsed.as_secs_f64(),
+            size as f64 / (1 << 20) as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
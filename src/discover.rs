@@ -0,0 +1,202 @@
+use std::{
+    cmp::Reverse,
+    io::{BufRead, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use chrono::NaiveDateTime;
+
+use crate::{input, timestamp};
+
+// the suffixes logrotate may add when compressing rotated files
+const COMPRESSION_SUFFIXES: &[&str] = &[".gz", ".zst", ".xz", ".bz2"];
+
+fn strip_compression(name: &str) -> &str {
+    COMPRESSION_SUFFIXES
+        .iter()
+        .find_map(|s| name.strip_suffix(s))
+        .unwrap_or(name)
+}
+
+// The rotation number of `mail.log.3` or `mail.log.3.gz`, if any
+fn rotation_number(name: &str) -> Option<u32> {
+    let name = strip_compression(name);
+    let (_, n) = name.rsplit_once('.')?;
+    n.parse().ok()
+}
+
+// Whether `name` looks like a rotated version of the `base` log file
+//
+// This recognizes `mail.log.1`, `mail.log.2.gz`, `mail.log-20210324.gz` and
+// `mail-2021-03-24.log.gz` as rotated versions of `mail.log`.
+fn is_rotated_version(base: &str, name: &str) -> bool {
+    let name = strip_compression(name);
+    if name == base {
+        // Only the compression differs
+        return true;
+    }
+    if let Some(rest) = name.strip_prefix(base) {
+        return match rest.strip_prefix('.').or_else(|| rest.strip_prefix('-')) {
+            Some(rest) => {
+                (!rest.is_empty() && rest.bytes().all(|c| c.is_ascii_digit()))
+                    || timestamp::in_name(rest).is_some()
+            }
+            None => false,
+        };
+    }
+    let (stem, ext) = match base.rfind('.') {
+        Some(dot) => base.split_at(dot),
+        None => (base, ""),
+    };
+    name.strip_prefix(stem)
+        .and_then(|r| r.strip_prefix('-'))
+        .and_then(|r| r.strip_suffix(ext))
+        .map(|middle| timestamp::in_name(middle).is_some())
+        .unwrap_or(false)
+}
+
+// Find the rotated versions of `base` that live next to it
+pub fn rotated_versions(base: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let base_name = match base.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n,
+        None => return Ok(Vec::new()),
+    };
+    let dir = match base.parent() {
+        Some(d) if d.as_os_str().is_empty() => Path::new("."),
+        Some(d) => d,
+        None => return Ok(Vec::new()),
+    };
+    let mut res = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("listing directory {:?}", dir))? {
+        let entry = entry.with_context(|| format!("listing directory {:?}", dir))?;
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => continue,
+        };
+        if name != base_name && is_rotated_version(base_name, name) {
+            res.push(base.with_file_name(name));
+        }
+    }
+    Ok(res)
+}
+
+// The timestamp of the first line of the file, if it can be read without
+// consuming it, which is only the case of regular files
+pub fn first_timestamp(file: &Path) -> Option<NaiveDateTime> {
+    if !input::is_regular(file) {
+        return None;
+    }
+    let f = input::open(file).ok()?;
+    let (_, f) = input::decompress(f).ok()?;
+    let mut line = Vec::new();
+    f.take(4096).read_until(b'\n', &mut line).ok()?;
+    timestamp::parse(&line, timestamp::reference(file))
+}
+
+// Sort log files from the oldest to the most recent
+//
+// Files are placed by the date embedded in their name if any, or else by when
+// they are `known` to start (eg. from their summary), or else by the
+// timestamp of their first line. Rotation numbers (the higher the older)
+// break the ties, and the standard input, named pipes and the like always come
+// last, as they are likely to be the most recent and cannot be peeked into.
+pub fn sort_chronologically(files: &mut [PathBuf], known: impl Fn(&Path) -> Option<NaiveDateTime>) {
+    files.sort_by_cached_key(|file| {
+        let name = file
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("")
+            .to_string();
+        let is_stream = input::is_stdin(file) || (file.exists() && !input::is_regular(file));
+        let start = if is_stream {
+            None
+        } else {
+            timestamp::in_name(&name)
+                .map(|d| d.and_hms_opt(0, 0, 0).expect("midnight is a valid time"))
+                .or_else(|| known(file))
+                .or_else(|| first_timestamp(file))
+        };
+        (
+            is_stream,
+            start.is_none(),
+            start,
+            Reverse(rotation_number(&name)),
+            file.clone(),
+        )
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotated_versions_of_a_name() {
+        for name in [
+            "mail.log.1",
+            "mail.log.2.gz",
+            "mail.log.gz",
+            "mail.log-20210324",
+            "mail.log-20210324.zst",
+            "mail.log.2021-03-24",
+            "mail-2021-03-24.log.gz",
+            "mail-20210324.log",
+        ]
+        .iter()
+        {
+            assert!(is_rotated_version("mail.log", name), "{}", name);
+        }
+        for name in [
+            "mail.log.",
+            "mail.log.old",
+            "mail.log1",
+            "mail.logs",
+            "mail.info.1",
+            "mail-old.log",
+            "mail-2021-03-24.info",
+        ]
+        .iter()
+        {
+            assert!(!is_rotated_version("mail.log", name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn sort_oldest_first() {
+        // none of these exist, so only their names and what is known of them
+        // tell how old they are
+        let mut files = [
+            "-",
+            "mail.log",
+            "mail.log.1",
+            "mail.info",
+            "mail.log.2.gz",
+            "mail.log-20210324.gz",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+        sort_chronologically(&mut files, |file| {
+            (file == Path::new("mail.info")).then(|| {
+                NaiveDateTime::parse_from_str("2021-03-25T12:00:00", "%Y-%m-%dT%H:%M:%S").unwrap()
+            })
+        });
+        let names = files
+            .iter()
+            .map(|f| f.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "mail.log-20210324.gz",
+                "mail.info",
+                "mail.log.2.gz",
+                "mail.log.1",
+                "mail.log",
+                "-"
+            ]
+        );
+    }
+}
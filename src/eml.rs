@@ -0,0 +1,198 @@
+use std::{io::Read, path::Path};
+
+use anyhow::Context;
+use chrono::NaiveDateTime;
+
+use crate::{input, message_id};
+
+// what a copy of a mail tells about where to find it in the logs
+#[derive(Debug, Default)]
+pub struct Eml {
+    // from the Message-ID and Resent-Message-ID headers, without brackets
+    pub message_ids: Vec<String>,
+
+    // from the Received headers
+    pub received: Vec<Received>,
+}
+
+// a hop of the mail, as told by a Received header
+#[derive(Debug, PartialEq, Eq)]
+pub struct Received {
+    pub host: String,                // the host that received the mail
+    pub id: String,                  // the id it gave it, a postfix id for postfix hosts
+    pub time: Option<NaiveDateTime>, // when, in local time, if the date could be parsed
+}
+
+// `text` without its (possibly nested) comments in parentheses
+fn strip_comments(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => res.push(c),
+            _ => (),
+        }
+    }
+    res
+}
+
+// The host, id and date of a Received header, like
+// `from … by mx1.example.org (Postfix) with ESMTPS id 4F3A2B1C9D for <…>; date`
+fn parse_received(value: &str) -> Option<Received> {
+    let (value, date) = value.rsplit_once(';').unwrap_or((value, ""));
+    let time = chrono::DateTime::parse_from_rfc2822(strip_comments(date).trim())
+        .ok()
+        .map(|t| t.with_timezone(&chrono::Local).naive_local());
+    let value = strip_comments(value);
+    let words = value.split_whitespace().collect::<Vec<&str>>();
+    let by = words.iter().position(|w| w.eq_ignore_ascii_case("by"))?;
+    let host = words.get(by + 1)?;
+    let id = words[by..]
+        .iter()
+        .position(|w| w.eq_ignore_ascii_case("id"))?;
+    let id = words.get(by + id + 1)?;
+    Some(Received {
+        host: host.to_string(),
+        id: id.to_string(),
+        time,
+    })
+}
+
+impl Eml {
+    // Parse the headers of a mail, up to the first empty line
+    pub fn parse(text: &str) -> Eml {
+        // unfold the headers, that continue on lines starting with a space
+        let mut headers: Vec<String> = Vec::new();
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                break;
+            }
+            match headers.last_mut() {
+                Some(h) if line.starts_with([' ', '\t']) => {
+                    h.push(' ');
+                    h.push_str(line.trim_start());
+                }
+                _ => headers.push(line.to_string()),
+            }
+        }
+
+        let mut res = Eml::default();
+        for h in headers.iter() {
+            let (name, value) = match h.split_once(':') {
+                Some(h) => h,
+                None => continue,
+            };
+            let name = name.trim().to_ascii_lowercase();
+            if name == "message-id" || name == "resent-message-id" {
+                let (id, _) = message_id::normalize(value);
+                if !id.is_empty() && !res.message_ids.contains(&id) {
+                    res.message_ids.push(id);
+                }
+            } else if name == "received" {
+                if let Some(received) = parse_received(value) {
+                    if !res.received.contains(&received) {
+                        res.received.push(received);
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    // Read a mail from a file, `-` being the standard input
+    pub fn read(file: &Path) -> anyhow::Result<Eml> {
+        let mut text = Vec::new();
+        input::open(file)
+            .and_then(|mut f| f.read_to_end(&mut text))
+            .with_context(|| format!("reading mail {:?}", file))?;
+        Ok(Eml::parse(&String::from_utf8_lossy(&text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    #[test]
+    fn strip_nested_comments() {
+        assert_eq!(strip_comments("a (b (c) d) e (f"), "a  e ");
+        assert_eq!(strip_comments("a) b"), "a b");
+    }
+
+    #[test]
+    fn received() {
+        let received = parse_received(
+            " from client.example.org (client.example.org [203.0.113.7])\t\
+             by mx1.example.org (Postfix) with ESMTPS id 4F3A2B1C9D\t\
+             for <bob@example.org>; Sun, 1 Mar 2026 14:00:00 +0100 (CET)",
+        )
+        .unwrap();
+        assert_eq!(received.host, "mx1.example.org");
+        assert_eq!(received.id, "4F3A2B1C9D");
+        let time = Utc.with_ymd_and_hms(2026, 3, 1, 13, 0, 0).unwrap();
+        assert_eq!(
+            received.time,
+            Some(time.with_timezone(&chrono::Local).naive_local())
+        );
+    }
+
+    #[test]
+    fn received_without_id() {
+        assert_eq!(
+            parse_received("from a by b; Sun, 1 Mar 2026 14:00:00 +0100"),
+            None
+        );
+        assert_eq!(parse_received("from a with id 4F3A2B1C9D"), None);
+        // the id in a comment is not the one of the host
+        assert_eq!(parse_received("by b (id 4F3A2B1C9D) with SMTP"), None);
+        let received = parse_received("by b with SMTP id 4F3A2B1C9D; yesterday").unwrap();
+        assert_eq!(received.time, None);
+    }
+
+    #[test]
+    fn folded_headers() {
+        let eml = Eml::parse(
+            "Received: from client.example.org\r\n\
+             \tby mx1.example.org (Postfix) with ESMTPS\r\n\
+             \x20 id 4F3A2B1C9D; Sun, 1 Mar 2026 14:00:00 +0100\r\n\
+             Message-ID:\r\n\
+             \x20<abc@example.org>\r\n\
+             Subject: hello\r\n\
+             \r\n\
+             Message-ID: <in-the-body@example.org>\r\n",
+        );
+        assert_eq!(eml.message_ids, vec![String::from("abc@example.org")]);
+        assert_eq!(eml.received.len(), 1);
+        assert_eq!(eml.received[0].host, "mx1.example.org");
+        assert_eq!(eml.received[0].id, "4F3A2B1C9D");
+    }
+
+    #[test]
+    fn several_ids() {
+        let eml = Eml::parse(
+            "Received: by relay.example.org (Postfix) id 1111111111; Sun, 1 Mar 2026 14:00:01 +0100\n\
+             Received: by mx1.example.org (Postfix) id 4F3A2B1C9D; Sun, 1 Mar 2026 14:00:00 +0100\n\
+             resent-message-id: <def@example.org>\n\
+             Message-Id: <abc@example.org>\n\
+             Message-ID: <abc@example.org>\n",
+        );
+        assert_eq!(
+            eml.message_ids,
+            vec![
+                String::from("def@example.org"),
+                String::from("abc@example.org")
+            ]
+        );
+        let hosts = eml
+            .received
+            .iter()
+            .map(|r| r.host.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(hosts, vec!["relay.example.org", "mx1.example.org"]);
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{BufRead, BufReader},
+    os::unix::fs::MetadataExt,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use console::style;
+
+use crate::{input, Delivery, Details, ParsedLine, State};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// a log file being tailed
+struct Tail {
+    // the path to the log file, that may get rotated under our feet
+    path: PathBuf,
+
+    // the currently open file, if it exists
+    reader: Option<Box<dyn BufRead>>,
+
+    // the inode of the currently open file, to notice rotations
+    inode: Option<u64>,
+
+    // how many bytes were read from the currently open file, to notice truncations
+    pos: u64,
+
+    // the beginning of a line whose end has not been written yet
+    partial: Vec<u8>,
+
+    // whether we reached the end of a stream that will never grow again
+    ended: bool,
+
+    // everything read so far, to be able to show the lines that were logged
+    // before we knew they were related to the message
+    state: State,
+}
+
+impl Tail {
+    fn new(index: usize, path: PathBuf) -> Tail {
+        Tail {
+            state: State::new(index, path.clone()),
+            path,
+            reader: None,
+            inode: None,
+            pos: 0,
+            partial: Vec::new(),
+            ended: false,
+        }
+    }
+
+    fn open(&mut self) -> anyhow::Result<()> {
+        if input::is_stdin(&self.path) {
+            self.reader = Some(Box::new(BufReader::new(std::io::stdin())));
+            return Ok(());
+        }
+        match std::fs::File::open(&self.path) {
+            Ok(f) => {
+                self.inode = Some(
+                    f.metadata()
+                        .with_context(|| {
+                            format!("retrieving metadata for log file {:?}", self.path)
+                        })?
+                        .ino(),
+                );
+                self.reader = Some(Box::new(BufReader::new(f)));
+                self.pos = 0;
+                Ok(())
+            }
+            // The file may be in the middle of being rotated
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("opening log file {:?}", self.path)),
+        }
+    }
+
+    // Read all the complete lines currently available in the open file
+    fn drain(&mut self, lines: &mut Vec<Vec<u8>>) -> anyhow::Result<()> {
+        let reader = match &mut self.reader {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        loop {
+            let path = &self.path;
+            let read = reader
+                .read_until(b'\n', &mut self.partial)
+                .with_context(|| format!("reading file {:?}", path))?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.pos += read as u64;
+            if self.partial.ends_with(b"\n") {
+                lines.push(std::mem::take(&mut self.partial));
+            }
+        }
+    }
+
+    // Read all the new complete lines, following the file across rotations
+    fn poll(&mut self) -> anyhow::Result<Vec<Vec<u8>>> {
+        let mut lines = Vec::new();
+        if self.ended {
+            return Ok(lines);
+        }
+        if self.reader.is_none() {
+            self.open()?;
+        }
+        self.drain(&mut lines)?;
+
+        if input::is_stdin(&self.path) {
+            // Having drained the standard input means it was closed
+            self.ended = true;
+            return Ok(lines);
+        }
+        let rotated = match std::fs::metadata(&self.path) {
+            Ok(meta) => Some(meta.ino()) != self.inode || meta.len() < self.pos,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("retrieving metadata for log file {:?}", self.path))
+            }
+        };
+        if rotated {
+            // Pick up whatever got written to the old file in the meantime
+            self.drain(&mut lines)?;
+            self.partial.clear();
+            self.open()?;
+            self.drain(&mut lines)?;
+        }
+        Ok(lines)
+    }
+}
+
+// what we saw happen to one of the followed postfix transactions
+#[derive(Default)]
+struct Progress {
+    // the number of recipients, as announced by qmgr
+    nrcpt: Option<usize>,
+
+    // recipient => last delivery attempt
+    recipients: BTreeMap<String, Delivery>,
+
+    // whether postfix is done with this transaction
+    removed: bool,
+}
+
+// the set of postfix transactions we know are related to the message
+struct Tracker<'a> {
+    message_id: &'a str,
+    bracketed_message_id: String,
+    tracked: HashSet<String>,
+    progress: BTreeMap<String, Progress>,
+}
+
+impl<'a> Tracker<'a> {
+    fn new(message_id: &'a str) -> Tracker<'a> {
+        Tracker {
+            message_id,
+            bracketed_message_id: String::from("<") + message_id + ">",
+            tracked: HashSet::new(),
+            progress: BTreeMap::new(),
+        }
+    }
+
+    fn is_related(&self, details: &Details) -> bool {
+        let mid_matches = details
+            .message_id
+            .as_ref()
+            .map(|m| m == self.message_id || *m == self.bracketed_message_id)
+            .unwrap_or(false);
+        let comes_from_tracked = details
+            .previous_id
+            .as_ref()
+            .map(|p| self.tracked.contains(p))
+            .unwrap_or(false);
+        mid_matches || comes_from_tracked
+    }
+
+    // Handle a line that was just pushed into the state of one of the `tails`
+    fn handle(&mut self, tails: &[Tail], parsed: ParsedLine, line: &[u8]) {
+        // Follow mode watches the logs of a single host, so the bare postfix
+        // ids are enough to tell transactions apart
+        let (id, details) = match parsed {
+            ParsedLine::Postfix { id, details } => (id.id, details),
+            ParsedLine::Useless | ParsedLine::Unknown => return,
+        };
+        if self.tracked.contains(&id) {
+            let text = String::from_utf8_lossy(&line[..line.len() - 1]);
+            self.show(tails, &id, &details, &text);
+        } else if self.is_related(&details) {
+            // The line was already pushed, so it is part of the backlog
+            self.track(tails, id, None);
+        }
+    }
+
+    // Start following a transaction, showing everything already logged about it
+    fn track(&mut self, tails: &[Tail], id: String, from: Option<&str>) {
+        if !self.tracked.insert(id.clone()) {
+            return;
+        }
+        println!(
+            "{}",
+            style(match from {
+                Some(from) => format!("── following {}, coming from {} ──", id, from),
+                None => format!("── following {} ──", id),
+            })
+            .cyan()
+        );
+        for t in tails {
+            for c in t.state.chunks.iter().filter(|c| c.queue_id.id == id) {
+                for &l in c.lines.iter() {
+                    let text = &t.state.lines[l.line].text;
+                    if let ParsedLine::Postfix { details, .. } =
+                        ParsedLine::parse(format!("{}\n", text).as_bytes())
+                    {
+                        self.show(tails, &id, &details, text);
+                    }
+                }
+            }
+        }
+    }
+
+    // Print a line of a followed transaction and record what it says
+    fn show(&mut self, tails: &[Tail], id: &str, details: &Details, text: &str) {
+        println!("{} {}", style(format!("[{}]", id)).bold(), text);
+
+        let progress = self.progress.entry(id.to_string()).or_default();
+        if let Some(nrcpt) = details.nrcpt {
+            progress.nrcpt = Some(nrcpt);
+        }
+        if let Some(d) = &details.delivery {
+            progress.recipients.insert(d.to.clone(), d.clone());
+        }
+        progress.removed |= details.removed;
+
+        if let Some(nid) = &details.next_id {
+            self.track(tails, nid.clone(), Some(id));
+        }
+    }
+
+    // Whether all the transactions that showed up in the logs are over
+    //
+    // Transactions that never showed up are assumed to be on other hosts.
+    fn is_done(&self) -> bool {
+        !self.progress.is_empty() && self.progress.values().all(|p| p.removed)
+    }
+
+    fn summary(&self) {
+        println!();
+        for (id, p) in self.progress.iter() {
+            let finals = p.recipients.values().filter(|d| d.is_final()).count();
+            println!(
+                "  {}: {}{}/{} recipient(s) reached a final status",
+                style(id).bold(),
+                if p.removed { "done, " } else { "" },
+                finals,
+                p.nrcpt
+                    .map(|n| n.to_string())
+                    .unwrap_or_else(|| String::from("?")),
+            );
+            for (to, d) in p.recipients.iter() {
+                println!("    {} {} (relay={})", to, d.status, d.relay);
+            }
+        }
+    }
+}
+
+// Watch the log files for lines about `message_id`, until all its recipients
+// reached a final status or `timeout` expires
+pub fn follow(message_id: &str, files: &[PathBuf], timeout: Duration) -> anyhow::Result<()> {
+    let mut tails = files
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(i, f)| Tail::new(i, f))
+        .collect::<Vec<_>>();
+    let mut tracker = Tracker::new(message_id);
+    let deadline = Instant::now() + timeout;
+
+    eprintln!(
+        "following {:?} for message-id ‘{}’ (giving up after {}s)",
+        files,
+        message_id,
+        timeout.as_secs()
+    );
+    loop {
+        for i in 0..tails.len() {
+            for line in tails[i].poll()? {
+                let parsed = ParsedLine::parse(&line);
+                let _ = tails[i].state.push(parsed.clone(), &line);
+                tracker.handle(&tails, parsed, &line);
+            }
+        }
+
+        if tracker.is_done() {
+            tracker.summary();
+            return Ok(());
+        }
+        if tails.iter().all(|t| t.ended) {
+            tracker.summary();
+            bail!("all the inputs ended before all recipients reached a final status");
+        }
+        if Instant::now() >= deadline {
+            tracker.summary();
+            bail!(
+                "gave up after {}s before all recipients reached a final status",
+                timeout.as_secs()
+            );
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::Context;
+
+// the file name that stands for the standard input
+pub const STDIN: &str = "-";
+
+pub fn is_stdin(file: &Path) -> bool {
+    file.as_os_str() == STDIN
+}
+
+// Whether the file is a regular file, that can be read more than once unlike
+// the standard input, named pipes, sockets, etc.
+pub fn is_regular(file: &Path) -> bool {
+    !is_stdin(file) && std::fs::metadata(file).is_ok_and(|m| m.is_file())
+}
+
+// Open a log file, or the standard input if the file is `-`
+pub fn open(file: &Path) -> std::io::Result<Box<dyn Read>> {
+    open_at(file, 0)
+}
+
+// Open a log file at `offset`, which must be 0 unless the file is seekable
+pub fn open_at(file: &Path, offset: u64) -> std::io::Result<Box<dyn Read>> {
+    if is_stdin(file) {
+        Ok(Box::new(std::io::stdin()))
+    } else {
+        let mut f = std::fs::File::open(file)?;
+        if offset > 0 {
+            f.seek(SeekFrom::Start(offset))?;
+        }
+        Ok(Box::new(f))
+    }
+}
+
+// Whether the file is a regular uncompressed file, that can be seeked into
+pub fn is_seekable_plain(file: &Path) -> std::io::Result<bool> {
+    if is_stdin(file) || !std::fs::metadata(file)?.is_file() {
+        return Ok(false);
+    }
+    let mut magic = Vec::with_capacity(Compression::MAGIC_LEN);
+    std::fs::File::open(file)?
+        .take(Compression::MAGIC_LEN as u64)
+        .read_to_end(&mut magic)?;
+    Ok(Compression::sniff(&magic) == Compression::None)
+}
+
+// The number of bytes that will be read from the file, if known in advance
+//
+// This is not the case for the standard input, named pipes, sockets, etc.
+pub fn size(file: &Path) -> std::io::Result<Option<u64>> {
+    if is_stdin(file) {
+        return Ok(None);
+    }
+    let meta = std::fs::metadata(file)?;
+    Ok(if meta.is_file() {
+        Some(meta.len())
+    } else {
+        None
+    })
+}
+
+// the compression formats we know how to read, as detected from the
+// first bytes of the file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+    None,
+}
+
+impl Compression {
+    // the longest magic number we need to look at
+    const MAGIC_LEN: usize = 6;
+
+    fn sniff(magic: &[u8]) -> Compression {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if magic.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+}
+
+// Wrap `reader` with the right decompressor, based on its first bytes
+//
+// Files that do not look like any known compression format are read as
+// plain text, so that renamed or extension-less files still work.
+pub fn decompress<R: 'static + Read>(
+    mut reader: R,
+) -> anyhow::Result<(Compression, Box<dyn BufRead>)> {
+    // Read the magic number by hand rather than peeking in a BufReader,
+    // as a single read on a pipe may return fewer bytes than we need
+    let mut magic = Vec::with_capacity(Compression::MAGIC_LEN);
+    (&mut reader)
+        .take(Compression::MAGIC_LEN as u64)
+        .read_to_end(&mut magic)
+        .context("reading the magic number")?;
+    let compression = Compression::sniff(&magic);
+    let reader = BufReader::new(std::io::Cursor::new(magic).chain(reader));
+
+    let res: Box<dyn BufRead> = match compression {
+        Compression::Gzip => Box::new(BufReader::new(
+            // gzip files may be made of multiple concatenated members
+            libflate::gzip::MultiDecoder::new(reader).context("reading the gzip header")?,
+        )),
+        Compression::Zstd => Box::new(BufReader::new(
+            zstd::Decoder::with_buffer(reader).context("reading the zstd header")?,
+        )),
+        Compression::Xz => Box::new(BufReader::new(xz2::bufread::XzDecoder::new_multi_decoder(
+            reader,
+        ))),
+        Compression::Bzip2 => Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+        Compression::None => Box::new(reader),
+    };
+    Ok((compression, res))
+}
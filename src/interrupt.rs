@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigint(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+    // A second Ctrl-C kills us as usual, eg. if the merge takes too long
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+// Catch Ctrl-C, so that reading the log files can stop and what was already
+// read can still be displayed
+pub fn install() -> std::io::Result<()> {
+    let handler = on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // Safety: the handler only does async-signal-safe things
+    if unsafe { libc::signal(libc::SIGINT, handler) } == libc::SIG_ERR {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Whether Ctrl-C was hit since `install`
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
@@ -1,378 +1,752 @@
 use std::{
     collections::{BTreeSet, HashMap, HashSet},
     io::BufRead,
-    path::PathBuf,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{bail, ensure, Context};
+use chrono::{Duration as TimeDelta, NaiveDateTime};
 use console::style;
 use rayon::prelude::*;
 use structopt::StructOpt;
 
+mod discover;
+mod eml;
+mod follow;
+mod input;
+mod interrupt;
+mod message_id;
+mod prefilter;
+mod query;
+mod range;
+mod search;
+mod spill;
+mod summary;
+mod timestamp;
+
 const DEFAULT_LOG_LOCATION: &str = "/var/log/**/mail*.log";
+const DEFAULT_FOLLOW_LOCATION: &str = "/var/log/mail.log";
+const DEFAULT_FOLLOW_TIMEOUT: u64 = 600;
+
+// Past this many mails found, only a line is shown for each of them
+const MAX_DRAWN_MAILS: usize = 10;
+
+// Past this many mails matching a pattern, the others are left out
+const MAX_MATCHED_MAILS: usize = 100;
+
+// Past this many bytes, a state is worth moving to disk once the memory limit
+// is exceeded
+const SPILL_BATCH: usize = 1 << 20;
+
+// Past this many hours without a line, a postfix id is considered reused
+const QUEUE_ID_MAX_GAP_HOURS: i64 = 24;
+
+// How far apart the clocks of the hosts relaying a mail to each other may be
+const RELAY_MAX_SKEW_MINUTES: i64 = 5;
 
 #[derive(Debug, structopt::StructOpt)]
 #[structopt(author, about = "Parse log files looking for what a mail went through")]
 struct Opt {
-    /// Message-id to look for in the log files
-    message_id: String,
+    /// Message-id to look for in the log files, which may be followed by more of them (as long
+    /// as they hold an `@` and are not files)
+    #[structopt(required_unless_one = &["from", "to", "queue-id", "client", "sasl-user", "ids-from", "query", "eml"])]
+    message_id: Option<String>,
+
+    /// Also look for the message-ids listed in this file, one per line, all in one pass
+    #[structopt(long, parse(from_os_str),
+                conflicts_with_all = &["follow", "from", "to", "queue-id", "client", "sasl-user"])]
+    ids_from: Option<PathBuf>,
+
+    /// Look for the transaction with this postfix id instead of a message-id, which is also
+    /// done when the message-id looks like one
+    #[structopt(long, conflicts_with_all = &["follow", "from", "to"])]
+    queue_id: Option<String>,
+
+    /// Look for the mails submitted by smtpd clients with this address, in this range of
+    /// addresses (eg. 203.0.113.0/24) or with this host name, instead of a message-id
+    #[structopt(long, parse(try_from_str = search::Pattern::client),
+                conflicts_with_all = &["follow", "from", "to", "queue-id"])]
+    client: Option<search::Pattern>,
+
+    /// Look for the mails submitted by smtpd clients authenticated as this SASL user, instead
+    /// of a message-id
+    #[structopt(long, conflicts_with_all = &["follow", "from", "to", "queue-id", "client"])]
+    sasl_user: Option<String>,
+
+    /// Look for the message-ids or --from/--to addresses that contain the given one, eg. when
+    /// it was cut
+    #[structopt(long, conflicts_with_all = &["follow", "queue-id", "client", "sasl-user"])]
+    substring: bool,
+
+    /// Take the message-id or --from/--to address as a glob, like `*@example.org`
+    #[structopt(long, conflicts_with_all = &["follow", "queue-id", "client", "sasl-user", "substring"])]
+    glob: bool,
+
+    /// Take the message-id or --from/--to address as a regular expression
+    #[structopt(long, conflicts_with_all = &["follow", "queue-id", "client", "sasl-user", "substring", "glob"])]
+    regex: bool,
+
+    /// Look for the mails sent by this address instead of a message-id, as the envelope sender
+    #[structopt(long, conflicts_with = "follow")]
+    from: Option<String>,
+
+    /// Look for the mails delivered to this address instead of a message-id, be it the final
+    /// recipient or the one before alias expansion, highlighting its deliveries; along with
+    /// --from, only the mails between them, eg. to find one that has no message-id
+    #[structopt(long, conflicts_with = "follow")]
+    to: Option<String>,
+
+    /// Look for the mails matching this query instead of a message-id, like
+    /// `from:*@example.org to:gmail.com status:bounced after:2026-10-01` (see the README)
+    #[structopt(long, parse(try_from_str = query::Query::parse),
+                conflicts_with_all = &["follow", "from", "to", "queue-id", "client", "sasl-user",
+                                       "ids-from", "substring", "glob", "regex"])]
+    query: Option<query::Query>,
 
-    /// Log files into which to look (gzipped or not) [default: /var/log/**/mail*.log]
+    /// Look for the mail a copy of which is in this file (`-` for the standard input), by the
+    /// message-ids in its headers and the postfix ids our hosts added to its Received headers
+    #[structopt(long, parse(from_os_str),
+                conflicts_with_all = &["follow", "from", "to", "queue-id", "client", "sasl-user",
+                                       "ids-from", "query", "substring", "glob", "regex"])]
+    eml: Option<PathBuf>,
+
+    /// Log files into which to look (plain, gzip, zstd, xz or bzip2), `-` for the standard
+    /// input [default: /var/log/**/mail*.log]
     #[structopt(parse(from_os_str))]
     files: Vec<PathBuf>,
+
+    /// Also look into the rotated versions of the log files (mail.log.1, mail.log.2.gz,
+    /// mail.log-20210324.gz, mail-2021-03-24.log.gz, etc.)
+    #[structopt(short, long)]
+    rotated: bool,
+
+    /// Watch the log files for new lines about the message as they get written, until all
+    /// its recipients reached a final status [default file: /var/log/mail.log]
+    #[structopt(short, long)]
+    follow: bool,
+
+    /// Number of seconds after which to give up following the message [default: 600]
+    #[structopt(long, requires = "follow")]
+    timeout: Option<u64>,
+
+    /// Abort on the first log file that cannot be read, instead of skipping it
+    #[structopt(long)]
+    fail_fast: bool,
+
+    /// Only look at what happened since this time (eg. 2021-03-24, "2021-03-24 10:00", 2h,
+    /// yesterday)
+    #[structopt(long, parse(try_from_str = range::parse_time))]
+    since: Option<NaiveDateTime>,
+
+    /// Only look at what happened until this time (same formats as --since)
+    #[structopt(long, parse(try_from_str = range::parse_time))]
+    until: Option<NaiveDateTime>,
+
+    /// How far beyond --since and --until to still read the logs, so as to follow the
+    /// transactions that cross these boundaries
+    #[structopt(long, default_value = "1h", parse(try_from_str = range::parse_duration))]
+    margin: TimeDelta,
+
+    /// Keep a summary next to each log file (.<name>.mailparse-summary), to skip the files
+    /// that are not about the message without reading them
+    #[structopt(long)]
+    summaries: bool,
+
+    /// Memory-map uncompressed log files rather than reading them, which is faster but
+    /// crashes if a file gets truncated while being read (eg. by logrotate's copytruncate),
+    /// so is best kept for the files that are no longer written to
+    #[structopt(long)]
+    mmap: bool,
+
+    /// Move the parsed log files to disk past this much memory (eg. 512M, 4G), which is slower
+    /// but lets huge archives be searched on small hosts
+    #[structopt(long, parse(try_from_str = spill::parse_size))]
+    memory_limit: Option<u64>,
 }
 
 #[derive(Clone, PartialEq, Eq)]
 enum ParsedLine {
     Postfix {
-        id: String,                  // the postfix transaction id
-        message_id: Option<String>,  // the message-id, if listed
-        previous_id: Option<String>, // the previous postfix transaction id
-        next_id: Option<String>,     // the next postfix transaction id
+        id: QueueId,           // the postfix transaction id
+        details: Box<Details>, // what else the line tells about the transaction
     },
 
     Unknown,
     Useless,
 }
 
+// the information a postfix log line can give about its transaction
+#[derive(Clone, Default, PartialEq, Eq)]
+struct Details {
+    message_id: Option<String>,    // the message-id, if listed
+    from: Option<String>,          // the sender, if listed
+    client: Option<String>,        // the smtpd client, as `name[address]`, if listed
+    sasl_username: Option<String>, // the authenticated user of the client, if any
+    previous_id: Option<String>,   // the previous postfix transaction id
+    next_id: Option<String>,       // the next postfix transaction id
+    nrcpt: Option<usize>,          // the number of recipients, as announced by qmgr
+    delivery: Option<Delivery>,    // the delivery attempt, if this line is one
+    removed: bool,                 // whether the queue file got removed
+    no_message_id: bool,           // whether the message-id is empty or was made up by postfix
+}
+
+impl Details {
+    // What the transaction can be looked up by, according to this line
+    fn keys(&self) -> Vec<Key> {
+        let mut res = Vec::new();
+        res.extend(self.message_id.iter().cloned().map(Key::MessageId));
+        res.extend(self.from.iter().map(|f| Key::sender(f)));
+        if let Some(d) = &self.delivery {
+            res.extend(
+                std::iter::once(&d.to)
+                    .chain(d.orig_to.iter())
+                    .map(|t| Key::recipient(t)),
+            );
+        }
+        if let Some(client) = &self.client {
+            // `name[address]`, possibly followed by `:port`
+            if let Some((name, rest)) = client.split_once('[') {
+                res.push(Key::ClientName(name.to_lowercase()));
+                let addr = rest.split(']').next().unwrap_or_default();
+                let addr = addr.strip_prefix("IPv6:").unwrap_or(addr);
+                res.extend(addr.parse().ok().map(Key::ClientAddr));
+            }
+        }
+        res.extend(self.sasl_username.iter().cloned().map(Key::SaslUser));
+        res
+    }
+}
+
+// a delivery attempt for one recipient
+#[derive(Clone, PartialEq, Eq)]
+struct Delivery {
+    to: String,              // the recipient
+    orig_to: Option<String>, // the recipient before alias expansion, if any
+    relay: String,           // where the mail was handed to
+    status: String,          // sent, deferred, bounced, etc.
+}
+
+// what transactions can be looked up by
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    MessageId(String),
+    Sender(String),     // lowercased, without the angle brackets
+    Recipient(String),  // same, be it the final or original recipient
+    QueueId(String),    // a postfix id, on any host
+    ClientName(String), // the host name of an smtpd client, lowercased
+    ClientAddr(IpAddr), // the address of an smtpd client
+    SaslUser(String),   // the user an smtpd client authenticated as
+}
+
+// An address as it is indexed, lowercased and without the angle brackets
+fn normalize_address(address: &str) -> String {
+    let address = address.trim();
+    address
+        .strip_prefix('<')
+        .and_then(|a| a.strip_suffix('>'))
+        .unwrap_or(address)
+        .to_lowercase()
+}
+
+impl Key {
+    // Roughly how much memory the key takes
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Key>()
+            + match self {
+                Key::MessageId(s)
+                | Key::Sender(s)
+                | Key::Recipient(s)
+                | Key::QueueId(s)
+                | Key::ClientName(s)
+                | Key::SaslUser(s) => s.len(),
+                Key::ClientAddr(_) => 0,
+            }
+    }
+
+    fn sender(address: &str) -> Key {
+        Key::Sender(normalize_address(address))
+    }
+
+    fn recipient(address: &str) -> Key {
+        Key::Recipient(normalize_address(address))
+    }
+
+    // Whether something that was given as a message-id rather looks like a
+    // postfix id, which message-ids hardly ever do as they hold an `@`
+    fn looks_like_queue_id(arg: &str) -> bool {
+        arg.len() >= 6 && arg.bytes().all(|c| b"0123456789ABCDEF".contains(&c))
+    }
+
+    // Whether a log file may be about this, according to its summary
+    fn may_be_in(&self, summary: &summary::Summary) -> bool {
+        match self {
+            Key::MessageId(m) => summary.may_have_message_id(m),
+            Key::Sender(s) => summary.may_have_sender(s),
+            Key::Recipient(r) => summary.may_have_recipient(r),
+            Key::QueueId(q) => summary.may_have_queue_id(q),
+            // summaries know nothing about clients
+            Key::ClientName(_) | Key::ClientAddr(_) | Key::SaslUser(_) => true,
+        }
+    }
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Key::MessageId(m) => write!(f, "message-id ‘{}’", m),
+            Key::Sender(s) => write!(f, "sender ‘{}’", s),
+            Key::Recipient(r) => write!(f, "recipient ‘{}’", r),
+            Key::QueueId(q) => write!(f, "postfix id ‘{}’", q),
+            Key::ClientName(n) => write!(f, "client ‘{}’", n),
+            Key::ClientAddr(a) => write!(f, "client ‘{}’", a),
+            Key::SaslUser(u) => write!(f, "SASL user ‘{}’", u),
+        }
+    }
+}
+
+impl Delivery {
+    // whether postfix will not try delivering to this recipient again
+    fn is_final(&self) -> bool {
+        self.status != "deferred"
+    }
+}
+
+// the beginnings of the postfix log lines that are about no transaction
+const NO_ID_PREFIXES: &[&str] = &[
+    "Anonymous TLS connection established from ",
+    "connect from ",
+    "lost connection after ",
+    "disconnect from ",
+    "Untrusted TLS connection established to ",
+    "Trusted TLS connection established to ",
+    "connect to ",
+    "Anonymous TLS connection established to ",
+    "statistics: ",
+    "NOQUEUE: ",
+    "SSL_accept error from ",
+    "Trusted TLS connection established from ",
+    "Untrusted TLS connection established from ",
+    "timeout after ",
+    "improper command pipelining after ",
+    "Verified TLS connection established to ",
+    "too many errors ",
+    "mapping DSN status ",
+    "SSL_connect error to ",
+];
+
+// the same, for the postfix warnings (after `warning: `)
+const NO_ID_WARNING_PREFIXES: &[&str] = &[
+    "SASL authentication failure: ",
+    "TLS library problem: ",
+    "valid_hostname: empty hostname",
+    "valid_hostname: misplaced delimiter: .",
+    "valid_hostname: invalid character ",
+    "malformed domain name in resource data of MX record for ",
+    "Illegal address syntax from ",
+    "numeric domain name in resource data of MX record for ",
+    "Connection concurrency limit exceeded: ",
+    "Message delivery request rate limit exceeded: ",
+    "non-SMTP command from ",
+    "no MX host for ",
+    "numeric hostname: ",
+];
+
+// Whether a message-id is one that postfix made up for a mail that had none,
+// like <20260303100000.1A2B3C4D5E@mx.example.org>
+fn is_generated_message_id(message_id: &str, queue_id: &str) -> bool {
+    let local = message_id
+        .trim_start_matches('<')
+        .split('@')
+        .next()
+        .unwrap_or_default();
+    match local.split_once('.') {
+        Some((time, id)) => {
+            time.len() == "YYYYMMDDHHMMSS".len()
+                && time.bytes().all(|b| b.is_ascii_digit())
+                && id == queue_id
+        }
+        None => false,
+    }
+}
+
+// A parser matching any of the `tags`
+fn any_tag<'a>(
+    tags: &'static [&'static str],
+) -> impl Fn(&'a [u8]) -> nom::IResult<&'a [u8], &'a [u8]> {
+    move |i: &'a [u8]| match tags.iter().find(|t| i.starts_with(t.as_bytes())) {
+        Some(t) => Ok((&i[t.len()..], &i[..t.len()])),
+        None => Err(nom::Err::Error(nom::error::Error::new(
+            i,
+            nom::error::ErrorKind::Tag,
+        ))),
+    }
+}
+
 impl ParsedLine {
     fn parse(line: &[u8]) -> ParsedLine {
+        if prefilter::is_useless(line) {
+            return ParsedLine::Useless;
+        }
+        ParsedLine::parse_all(line)
+    }
+
+    // Parse a line with the whole parser, whatever the prefilter tells
+    fn parse_all(line: &[u8]) -> ParsedLine {
         use nom::{
             branch::alt,
-            bytes::complete::{is_a, tag, take, take_until},
-            combinator::{eof, map, opt, rest, value},
-            sequence::{delimited, preceded, tuple},
+            bytes::complete::{is_a, tag, take, take_till, take_until},
+            combinator::{eof, flat_map, map, opt, recognize, rest, value},
+            sequence::{delimited, preceded, terminated, tuple},
         };
-        let res: nom::IResult<&[u8], ParsedLine> = preceded(
+        let res: nom::IResult<&[u8], ParsedLine> = flat_map(
             tuple((
-                take("Jan 10 00:00:00 ".len()), // skip the date
-                take_until(" "),                // skip the hostname
-                take(1usize),                   // and the space
+                alt((
+                    terminated(is_a("0123456789-T:.+Z"), tag(" ")), // skip the high-precision date
+                    take("Jan 10 00:00:00 ".len()),                 // or the traditional one
+                )),
+                take_until(" "), // the hostname
+                take(1usize),    // and the space
             )),
-            alt((
-                // TODO: clamsmtp and postlicyd don't show the
-                // message-id, but maybe we could fiddle with
-                // from/to/timestamp to approximate?
-                value(ParsedLine::Useless, tag("clamsmtp")),
-                value(ParsedLine::Useless, tag("postlicyd")),
-                // Postfix log line
-                preceded(
-                    tuple((
-                        tag("postfix"),
-                        take_until(" "), // ignore until the beginning of the log line itself
-                        take(1usize),    // and the space
-                    )),
-                    alt((
-                        // Log lines with no identifier
-                        value(
-                            ParsedLine::Useless,
-                            alt((
-                                tag("Anonymous TLS connection established from "),
-                                tag("connect from "),
-                                tag("lost connection after "),
-                                tag("disconnect from "),
-                                tag("Untrusted TLS connection established to "),
-                                tag("Trusted TLS connection established to "),
-                                tag("connect to "),
-                                tag("Anonymous TLS connection established to "),
-                                tag("statistics: "),
-                                tag("NOQUEUE: "),
-                                tag("SSL_accept error from "),
-                                tag("Trusted TLS connection established from "),
-                                tag("Untrusted TLS connection established from "),
-                                tag("timeout after "),
-                                tag("improper command pipelining after "),
-                                tag("Verified TLS connection established to "),
-                                tag("too many errors "),
-                                tag("mapping DSN status "),
-                                tag("SSL_connect error to "),
-                            )),
-                        ),
-                        // Warning log lines
-                        preceded(
-                            tag("warning: "),
+            |(_, host, _): (&[u8], &[u8], &[u8])| {
+                alt((
+                    // TODO: clamsmtp and postlicyd don't show the
+                    // message-id, but maybe we could fiddle with
+                    // from/to/timestamp to approximate?
+                    value(ParsedLine::Useless, tag("clamsmtp")),
+                    value(ParsedLine::Useless, tag("postlicyd")),
+                    // Postfix log line
+                    map(
+                        tuple((
+                            // the instance, ie. the syslog name (postfix, postfix-out, etc.)
+                            recognize(tuple((tag("postfix"), take_till(|c| c == b'/' || c == b' ')))),
+                            take_until(" "), // ignore until the beginning of the log line itself
+                            take(1usize),    // and the space
                             alt((
-                                // Warning log lines that begin with an identifier
-                                map(
-                                    tuple((
+                                // Log lines with no identifier
+                                value(None, any_tag(NO_ID_PREFIXES)),
+                                // Warning log lines
+                                preceded(
+                                    tag("warning: "),
+                                    alt((
+                                        // Warning log lines that begin with an identifier
                                         map(
-                                            is_a("0123456789ABCDEF"),
-                                            |s: &[u8]| String::from_utf8_lossy(s).to_string()
+                                            tuple((
+                                                map(
+                                                    is_a("0123456789ABCDEF"),
+                                                    |s: &[u8]| String::from_utf8_lossy(s).to_string()
+                                                ),
+                                                tag(": "),
+                                                alt((
+                                                    tag("message has been queued for "),
+                                                    tag("unreasonable virtual_alias_maps map expansion size for "),
+                                                    tag("multi-valued sender_canonical_maps entry for"),
+                                                    tag("queue file size limit exceeded"),
+                                                )),
+                                            )),
+                                            |(id, _, _)| Some((id, Details::default()))
+                                        ),
+                                        // Warning log lines that contain no identifier
+                                        value(None, any_tag(NO_ID_WARNING_PREFIXES)),
+                                        value(
+                                            None,
+                                            tuple((
+                                                tag("database "),
+                                                take_until(" "),
+                                                tag(" is older than source file "),
+                                            )),
+                                        ),
+                                        value(
+                                            None,
+                                            tuple((
+                                                tag("process "),
+                                                take_until(" "),
+                                                tag(" pid "),
+                                                is_a("0123456789"),
+                                                tag(" exit status "),
+                                            )),
+                                        ),
+                                        value(
+                                            None,
+                                            tuple((
+                                                take_until(":"),
+                                                tag(": SASL "),
+                                                alt((tag("PLAIN"), tag("LOGIN"))),
+                                                tag(" authentication "),
+                                                alt((tag("failed"), tag("aborted"))),
+                                            )),
+                                        ),
+                                        value(
+                                            None,
+                                            tuple((
+                                                take_until(":"),
+                                                tag(": bad command startup -- throttling\n"),
+                                                eof,
+                                            )),
+                                        ),
+                                        value(
+                                            None,
+                                            tuple((
+                                                alt((tag("hostname "), tag("host "))),
+                                                take_until(" "),
+                                                alt((
+                                                    tag(" does not resolve to address "),
+                                                    tag(" greeted me with my own hostname "),
+                                                    tag(" replied to HELO/EHLO with my own hostname "),
+                                                )),
+                                            )),
                                         ),
-                                        tag(": "),
-                                        alt((
-                                            tag("message has been queued for "),
-                                            tag("unreasonable virtual_alias_maps map expansion size for "),
-                                            tag("multi-valued sender_canonical_maps entry for"),
-                                            tag("queue file size limit exceeded"),
-                                        )),
-                                    )),
-                                    |(id, _, _)| ParsedLine::Postfix {
-                                        id,
-                                        message_id: None,
-                                        previous_id: None,
-                                        next_id: None,
-                                    }
-                                ),
-                                // Warning log lines that contain no identifier
-                                value(
-                                    ParsedLine::Useless,
-                                    alt((
-                                        tag("SASL authentication failure: "),
-                                        tag("TLS library problem: "),
-                                        tag("valid_hostname: empty hostname"),
-                                        tag("valid_hostname: misplaced delimiter: ."),
-                                        tag("valid_hostname: invalid character "),
-                                        tag("malformed domain name in resource data of MX record for "),
-                                        tag("Illegal address syntax from "),
-                                        tag("numeric domain name in resource data of MX record for "),
-                                        tag("Connection concurrency limit exceeded: "),
-                                        tag("Message delivery request rate limit exceeded: "),
-                                        tag("non-SMTP command from "),
-                                        tag("no MX host for "),
-                                        tag("numeric hostname: "),
-                                    )),
-                                ),
-                                value(
-                                    ParsedLine::Useless,
-                                    tuple((
-                                        tag("database "),
-                                        take_until(" "),
-                                        tag(" is older than source file "),
-                                    )),
-                                ),
-                                value(
-                                    ParsedLine::Useless,
-                                    tuple((
-                                        tag("process "),
-                                        take_until(" "),
-                                        tag(" pid "),
-                                        is_a("0123456789"),
-                                        tag(" exit status "),
-                                    )),
-                                ),
-                                value(
-                                    ParsedLine::Useless,
-                                    tuple((
-                                        take_until(":"),
-                                        tag(": SASL "),
-                                        alt((tag("PLAIN"), tag("LOGIN"))),
-                                        tag(" authentication "),
-                                        alt((tag("failed"), tag("aborted"))),
-                                    )),
-                                ),
-                                value(
-                                    ParsedLine::Useless,
-                                    tuple((
-                                        take_until(":"),
-                                        tag(": bad command startup -- throttling\n"),
-                                        eof,
-                                    )),
-                                ),
-                                value(
-                                    ParsedLine::Useless,
-                                    tuple((
-                                        alt((tag("hostname "), tag("host "))),
-                                        take_until(" "),
-                                        alt((
-                                            tag(" does not resolve to address "),
-                                            tag(" greeted me with my own hostname "),
-                                            tag(" replied to HELO/EHLO with my own hostname "),
-                                        )),
                                     )),
                                 ),
-                            )),
-                        ),
-                        // Log lines that contain an identifier
-                        map(
-                            delimited(
-                                tag("fatal: lock file bounce "),
-                                map(
-                                    is_a("0123456789ABCDEF"),
-                                    |s: &[u8]| String::from_utf8_lossy(s).to_string()
-                                ),
-                                tag(": Resource temporarily unavailable"),
-                            ),
-                            |id| ParsedLine::Postfix {
-                                id: id.to_string(),
-                                message_id: None,
-                                previous_id: None,
-                                next_id: None,
-                            },
-                        ),
-                        // Log lines that begin with an identifier
-                        map(
-                            tuple((
+                                // Log lines that contain an identifier
                                 map(
-                                    is_a("0123456789ABCDEF"),
-                                    |s: &[u8]| String::from_utf8_lossy(s).to_string()
-                                ),
-                                tag(": "),
-                                alt((
-                                    // Log lines with nothing
-                                    value((None, None, None), tuple((tag("removed\n"), eof))),
-                                    value(
-                                        (None, None, None),
-                                        alt((
-                                            tag("enabling PIX workarounds: "),
-                                            tag("lost connection with "),
-                                            tag("discard: "),
-                                            tag("reject: "),
-                                            tag("filter: "),
-                                            tag("Cannot start TLS: "),
-                                            tag("conversation with "),
-                                            tag("warn: RCPT from "),
-                                        )),
-                                    ),
-                                    value(
-                                        (None, None, None),
-                                        tuple((
-                                            tag("uid="),
-                                            is_a("0123456789"),
-                                            tag(" from=<"),
-                                            take_until(">"),
-                                            tag(">\n"),
-                                            eof,
-                                        )),
-                                    ),
-                                    value(
-                                        (None, None, None),
-                                        tuple((
-                                            tag("from=<"),
-                                            take_until(">"),
-                                            tag(">, size="),
-                                            is_a("0123456789"),
-                                            tag(", nrcpt="),
-                                            is_a("0123456789"),
-                                            tag(" (queue active)\n"),
-                                            eof,
-                                        )),
-                                    ),
-                                    value(
-                                        (None, None, None),
-                                        tuple((
-                                            tag("from=<"),
-                                            take_until(">"),
-                                            tag(">, status="),
-                                            take_until(","),
-                                            tag(", returned to sender"),
-                                        )),
-                                    ),
-                                    value(
-                                        (None, None, None),
-                                        tuple((
-                                            tag("client="),
-                                            is_a("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.:-[]"),
-                                            tag("\n"),
-                                            eof,
-                                        )),
-                                    ),
-                                    value(
-                                        (None, None, None),
-                                        tuple((
-                                            tag("client="),
-                                            take_until(","),
-                                            tag(", sasl_method="),
-                                            take_until(","),
-                                            tag(", sasl_username="),
-                                            is_a("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.-@"),
-                                            tag("\n"),
-                                            eof,
-                                        )),
-                                    ),
-                                    value(
-                                        (None, None, None),
-                                        tuple((
-                                            tag("host "),
-                                            take_until(" "),
-                                            alt((
-                                                tag(" said: "),
-                                                tag(" refused to talk to me: "),
-                                            )),
-                                        )),
-                                    ),
-                                    // Log lines with message-id's, previous id's and/or next id's
                                     delimited(
-                                        tuple((
-                                            opt(tag("resent-")), // consider resent-message-id like message-id
-                                            tag("message-id="),
-                                        )),
-                                        map(
-                                            take_until("\n"),
-                                            |message_id: &[u8]| (Some(String::from_utf8_lossy(message_id).to_string()), None, None),
-                                        ),
-                                        tuple((tag("\n"), eof)),
-                                    ),
-                                    delimited(
-                                        tag("sender non-delivery notification: "),
+                                        tag("fatal: lock file bounce "),
                                         map(
                                             is_a("0123456789ABCDEF"),
-                                            |next_id: &[u8]| (None, None, Some(String::from_utf8_lossy(next_id).to_string())),
+                                            |s: &[u8]| String::from_utf8_lossy(s).to_string()
                                         ),
-                                        tuple((tag("\n"), eof)),
+                                        tag(": Resource temporarily unavailable"),
                                     ),
-                                    delimited(
-                                        tuple((
-                                            tag("client="),
-                                            take_until(","),
-                                            tag(", orig_queue_id="),
-                                        )),
+                                    |id| Some((id, Details::default())),
+                                ),
+                                // Log lines that begin with an identifier
+                                map(
+                                    tuple((
                                         map(
                                             is_a("0123456789ABCDEF"),
-                                            |previous_id: &[u8]| (None, Some(String::from_utf8_lossy(previous_id).to_string()), None),
+                                            |s: &[u8]| String::from_utf8_lossy(s).to_string()
                                         ),
-                                        tuple((
-                                            tag(", orig_client="),
-                                            is_a("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.-[]"),
-                                            tag("\n"),
-                                            eof,
-                                        )),
-                                    ),
-                                    preceded(
-                                        tuple((
-                                            tag("to=<"),
-                                            take_until(">"),
-                                            opt(tuple((tag(">, orig_to=<"), take_until(">")))),
-                                            tag(">, relay="),
-                                            take_until(","),
-                                            opt(tuple((tag(", conn_use="), is_a("0123456789")))),
-                                            tag(", delay="),
-                                            is_a("0123456789."),
-                                            tag(", delays="),
-                                            is_a("0123456789./"),
-                                            tag(", dsn="),
-                                            is_a("0123456789."),
-                                            tag(", status="),
-                                            take_until(" "),
-                                            tag(" ("),
-                                        )),
+                                        tag(": "),
                                         alt((
-                                            delimited(
+                                            // Log lines with nothing
+                                            value(
+                                                Details {
+                                                    removed: true,
+                                                    ..Details::default()
+                                                },
+                                                tuple((tag("removed\n"), eof)),
+                                            ),
+                                            value(
+                                                Details::default(),
                                                 alt((
-                                                    tag("forwarded as "),
-                                                    tag("250 2.0.0 Ok: queued as "),
+                                                    tag("enabling PIX workarounds: "),
+                                                    tag("lost connection with "),
+                                                    tag("discard: "),
+                                                    tag("reject: "),
+                                                    tag("filter: "),
+                                                    tag("Cannot start TLS: "),
+                                                    tag("conversation with "),
+                                                    tag("warn: RCPT from "),
+                                                )),
+                                            ),
+                                            map(
+                                                tuple((
+                                                    tag("uid="),
+                                                    is_a("0123456789"),
+                                                    tag(" from=<"),
+                                                    take_until(">"),
+                                                    tag(">\n"),
+                                                    eof,
+                                                )),
+                                                |(_, _, _, from, _, _)| Details {
+                                                    from: Some(String::from_utf8_lossy(from).to_string()),
+                                                    ..Details::default()
+                                                },
+                                            ),
+                                            map(
+                                                tuple((
+                                                    tag("from=<"),
+                                                    take_until(">"),
+                                                    tag(">, size="),
+                                                    is_a("0123456789"),
+                                                    tag(", nrcpt="),
+                                                    is_a("0123456789"),
+                                                    tag(" (queue active)\n"),
+                                                    eof,
+                                                )),
+                                                |(_, from, _, _, _, nrcpt, _, _)| Details {
+                                                    from: Some(String::from_utf8_lossy(from).to_string()),
+                                                    nrcpt: String::from_utf8_lossy(nrcpt).parse().ok(),
+                                                    ..Details::default()
+                                                },
+                                            ),
+                                            value(
+                                                Details::default(),
+                                                tuple((
+                                                    tag("from=<"),
+                                                    take_until(">"),
+                                                    tag(">, status="),
+                                                    take_until(","),
+                                                    tag(", returned to sender"),
+                                                )),
+                                            ),
+                                            map(
+                                                tuple((
+                                                    tag("client="),
+                                                    is_a("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.:-[]"),
+                                                    tag("\n"),
+                                                    eof,
+                                                )),
+                                                |(_, client, _, _)| Details {
+                                                    client: Some(String::from_utf8_lossy(client).to_string()),
+                                                    ..Details::default()
+                                                },
+                                            ),
+                                            map(
+                                                tuple((
+                                                    tag("client="),
+                                                    take_until(","),
+                                                    tag(", sasl_method="),
+                                                    take_until(","),
+                                                    tag(", sasl_username="),
+                                                    is_a("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.-@"),
+                                                    tag("\n"),
+                                                    eof,
+                                                )),
+                                                |(_, client, _, _, _, user, _, _)| Details {
+                                                    client: Some(String::from_utf8_lossy(client).to_string()),
+                                                    sasl_username: Some(String::from_utf8_lossy(user).to_string()),
+                                                    ..Details::default()
+                                                },
+                                            ),
+                                            value(
+                                                Details::default(),
+                                                tuple((
+                                                    tag("host "),
+                                                    take_until(" "),
+                                                    alt((
+                                                        tag(" said: "),
+                                                        tag(" refused to talk to me: "),
+                                                    )),
+                                                )),
+                                            ),
+                                            // Log lines with message-id's, previous id's and/or next id's
+                                            delimited(
+                                                tuple((
+                                                    opt(tag("resent-")), // consider resent-message-id like message-id
+                                                    tag("message-id="),
+                                                )),
+                                                map(
+                                                    take_until("\n"),
+                                                    |message_id: &[u8]| match message_id {
+                                                        // when the mail has none and postfix is not told to add one
+                                                        b"" | b"<>" => Details {
+                                                            no_message_id: true,
+                                                            ..Details::default()
+                                                        },
+                                                        _ => Details {
+                                                            message_id: Some(String::from_utf8_lossy(message_id).to_string()),
+                                                            ..Details::default()
+                                                        },
+                                                    },
+                                                ),
+                                                tuple((tag("\n"), eof)),
+                                            ),
+                                            delimited(
+                                                tag("sender non-delivery notification: "),
+                                                map(
+                                                    is_a("0123456789ABCDEF"),
+                                                    |next_id: &[u8]| Details {
+                                                        next_id: Some(String::from_utf8_lossy(next_id).to_string()),
+                                                        ..Details::default()
+                                                    },
+                                                ),
+                                                tuple((tag("\n"), eof)),
+                                            ),
+                                            delimited(
+                                                tuple((
+                                                    tag("client="),
+                                                    take_until(","),
+                                                    tag(", orig_queue_id="),
                                                 )),
                                                 map(
                                                     is_a("0123456789ABCDEF"),
-                                                    |next_id: &[u8]| (None, None, Some(String::from_utf8_lossy(next_id).to_string()))
+                                                    |previous_id: &[u8]| Details {
+                                                        previous_id: Some(String::from_utf8_lossy(previous_id).to_string()),
+                                                        ..Details::default()
+                                                    },
                                                 ),
-                                                tuple((tag(")\n"), eof)),
+                                                tuple((
+                                                    tag(", orig_client="),
+                                                    is_a("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.-[]"),
+                                                    tag("\n"),
+                                                    eof,
+                                                )),
+                                            ),
+                                            map(
+                                                tuple((
+                                                    tuple((
+                                                        tag("to=<"),
+                                                        take_until(">"),
+                                                        opt(tuple((tag(">, orig_to=<"), take_until(">")))),
+                                                        tag(">, relay="),
+                                                        take_until(","),
+                                                        opt(tuple((tag(", conn_use="), is_a("0123456789")))),
+                                                        tag(", delay="),
+                                                        is_a("0123456789."),
+                                                        tag(", delays="),
+                                                        is_a("0123456789./"),
+                                                        tag(", dsn="),
+                                                        is_a("0123456789."),
+                                                        tag(", status="),
+                                                        take_until(" "),
+                                                        tag(" ("),
+                                                    )),
+                                                    alt((
+                                                        delimited(
+                                                            alt((
+                                                                tag("forwarded as "),
+                                                                tag("250 2.0.0 Ok: queued as "),
+                                                            )),
+                                                            map(
+                                                                is_a("0123456789ABCDEF"),
+                                                                |next_id: &[u8]| Some(String::from_utf8_lossy(next_id).to_string()),
+                                                            ),
+                                                            tuple((tag(")\n"), eof)),
+                                                        ),
+                                                        value(None, rest),
+                                                    )),
+                                                )),
+                                                |((_, to, orig_to, _, relay, _, _, _, _, _, _, _, _, status, _), next_id)| Details {
+                                                    next_id,
+                                                    delivery: Some(Delivery {
+                                                        to: String::from_utf8_lossy(to).to_string(),
+                                                        orig_to: orig_to.map(|(_, o)| String::from_utf8_lossy(o).to_string()),
+                                                        relay: String::from_utf8_lossy(relay).to_string(),
+                                                        status: String::from_utf8_lossy(status).to_string(),
+                                                    }),
+                                                    ..Details::default()
+                                                },
                                             ),
-                                            value((None, None, None), rest),
                                         )),
-                                    ),
-                                )),
+                                    )),
+                                    |(id, _, details)| Some((id, details)),
+                                ),
                             )),
-                            |(id, _, (message_id, previous_id, next_id))| ParsedLine::Postfix {
-                                id: id.to_string(),
-                                message_id: message_id,
-                                previous_id: previous_id,
-                                next_id: next_id,
-                            },
-                        ),
-                    )),
-                ),
-            )),
+                        )),
+                        move |(instance, _, _, parsed)| match parsed {
+                            Some((id, mut details)) => {
+                                details.no_message_id |= details
+                                    .message_id
+                                    .as_deref()
+                                    .is_some_and(|m| is_generated_message_id(m, &id));
+                                ParsedLine::Postfix {
+                                    id: QueueId {
+                                        host: String::from_utf8_lossy(host).to_string(),
+                                        instance: String::from_utf8_lossy(instance).to_string(),
+                                        id,
+                                    },
+                                    details: Box::new(details),
+                                }
+                            }
+                            None => ParsedLine::Useless,
+                        },
+                    ),
+                ))
+            },
         )(line);
         match res {
             Ok((_, res)) => res,
@@ -398,316 +772,1769 @@ impl ParsedLine {
     }
 }
 
-// all the data associated to one postfix id
+// a reference to a line of one of the log files
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct LineRef {
+    file: usize, // the index of the file, in chronological order
+    line: usize, // the index of the line in the state of the file
+}
+
+// a line of a log file that is worth displaying
+#[derive(Clone, Debug)]
+struct Line {
+    time: Option<NaiveDateTime>, // when the line was logged, if known
+    text: String,                // the line, apart from the trailing \n
+}
+
+// a postfix id, qualified by the postfix instance it belongs to
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct QueueId {
+    host: String,     // the host postfix runs on, as written in the logs
+    instance: String, // the syslog name of the postfix instance, as hosts may run several
+    id: String,       // the postfix id itself
+}
+
+impl std::fmt::Display for QueueId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.instance == "postfix" {
+            write!(f, "{}/{}", self.host, self.id)
+        } else {
+            write!(f, "{}/{}/{}", self.host, self.instance, self.id)
+        }
+    }
+}
+
+// The host a mail was relayed to, from the `relay=` field of a delivery, or
+// `None` if it stayed on the same host
+fn relay_host(relay: &str) -> Option<&str> {
+    // `local`, `virtual`, `private/dovecot-lmtp`, etc. have no address
+    let (host, address) = relay.split_once('[')?;
+    let address = address.split(']').next().unwrap_or("");
+    let is_loopback = host == "localhost"
+        || address.starts_with("127.")
+        || address == "::1"
+        || address == "ipv6:::1";
+    if is_loopback {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+// Whether the `relay=` host name of a delivery designates a host of the logs,
+// that may be written with or without its domain
+//
+// Short names are only compared when one of the names has no domain, as
+// `mx.example.net` is not our `mx.example.org`.
+fn is_same_host(log_host: &str, relay_host: &str) -> bool {
+    let (log_host, relay_host) = (
+        log_host.to_ascii_lowercase(),
+        relay_host.to_ascii_lowercase(),
+    );
+    match (log_host.split_once('.'), relay_host.split_once('.')) {
+        (Some((short, _)), None) => short == relay_host,
+        (None, Some((short, _))) => log_host == short,
+        _ => log_host == relay_host,
+    }
+}
+
+// the identity of a postfix transaction
+//
+// Postfix reuses its queue id's over time, so each incarnation of a queue id
+// is told apart by when it started, which does not depend on which part of
+// the logs is read, unlike its rank among the incarnations that were read.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct BlockId {
+    queue_id: QueueId,            // the postfix id
+    since: Option<NaiveDateTime>, // when its first line was logged, if known
+    same_second: usize,           // the number of previous incarnations that started then
+}
+
+impl std::fmt::Display for BlockId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.queue_id)?;
+        if let Some(since) = self.since {
+            write!(f, "@{}", since.format("%Y-%m-%dT%H:%M:%S"))?;
+        }
+        if self.same_second > 0 {
+            write!(f, "#{}", self.same_second + 1)?;
+        }
+        Ok(())
+    }
+}
+
+// Format a set of block id's like `{"4F3A2B1C9D", "5B6C7D8E9F"}`
+fn format_ids(ids: &BTreeSet<BlockId>) -> String {
+    let ids = ids
+        .iter()
+        .map(|id| format!("{:?}", id.to_string()))
+        .collect::<Vec<String>>();
+    format!("{{{}}}", ids.join(", "))
+}
+
+// Whether two successive lines of a postfix id are too far apart to be about
+// the same transaction
+fn is_queue_id_gap(earlier: Option<NaiveDateTime>, later: Option<NaiveDateTime>) -> bool {
+    match (earlier, later) {
+        (Some(earlier), Some(later)) => later - earlier > TimeDelta::hours(QUEUE_ID_MAX_GAP_HOURS),
+        _ => false,
+    }
+}
+
+// How far from `at` a transaction that went on from `first` to `last` was, or
+// None if it was further than `max`, unknown times being deemed close
+fn distance_within(
+    at: Option<NaiveDateTime>,
+    first: Option<NaiveDateTime>,
+    last: Option<NaiveDateTime>,
+    max: TimeDelta,
+) -> Option<TimeDelta> {
+    let distance = match (at, first, last) {
+        (Some(at), Some(first), _) if at < first => first - at,
+        (Some(at), _, Some(last)) if at > last => at - last,
+        _ => TimeDelta::zero(),
+    };
+    (distance <= max).then_some(distance)
+}
+
+// all the data associated to one postfix transaction
 #[derive(Clone, Debug)]
 struct Block {
-    // the postfix id of this block
-    id: String,
+    // the identity of this block
+    id: BlockId,
 
-    // the file in which this block was
-    file: PathBuf,
+    // all the lines of this transaction, in chronological order
+    lines: Vec<LineRef>,
+
+    // all the previous transactions
+    previous_ids: BTreeSet<BlockId>,
+
+    // all the next transactions
+    next_ids: BTreeSet<BlockId>,
+}
+
+// a mention of another postfix id, yet to be resolved to a transaction
+#[derive(Clone, Debug)]
+struct Link {
+    id: String,            // the other postfix id
+    relay: Option<String>, // the host it lives on, if it is not the same host
+    line: LineRef,         // the line it is mentioned in
+}
 
-    // all the lines in which this postfix-id appears
-    lines: Vec<usize>,
+// the consecutive lines of one postfix id within one log file
+#[derive(Clone, Debug)]
+struct Chunk {
+    // the postfix id of this chunk
+    queue_id: QueueId,
+
+    // all the lines in which this postfix id appears
+    lines: Vec<LineRef>,
 
-    // all the previous id's
-    previous_ids: HashSet<String>,
+    // all the previous postfix id's
+    previous_ids: Vec<Link>,
 
-    // all the next id's
-    next_ids: HashSet<String>,
+    // all the next postfix id's
+    next_ids: Vec<Link>,
+
+    // whether the queue file was removed at the end of the chunk
+    removed: bool,
 }
 
-#[derive(Clone)]
 struct State {
+    // the index of the file this refers to, in chronological order
+    index: usize,
+
     // the file this refers to
     file: PathBuf,
 
-    // all the lines in the log files
-    lines: Vec<String>,
+    // the time relative to which the year of syslog timestamps is guessed
+    reference: NaiveDateTime,
+
+    // all the lines in the log files
+    lines: Vec<Line>,
+
+    // message-id, sender, etc. => all the chunks where it appears
+    keys: HashMap<Key, Vec<usize>>,
+
+    // all the chunks, in the order they started
+    chunks: Vec<Chunk>,
+
+    // postfix-id => the chunk that is still going on for it
+    open_chunks: HashMap<QueueId, usize>,
+
+    // the times out of which lines are ignored
+    range: range::Range,
+
+    // whether we got past the end of the range, after which log files have
+    // nothing more of interest
+    past_range: bool,
+
+    // the memory all the states may use, if limited, and roughly how much of
+    // it this one uses
+    budget: Option<Arc<spill::Budget>>,
+    footprint: usize,
+
+    // the chunks that were moved to disk to stay within the budget, before
+    // the ones above
+    spill: Option<spill::Spill>,
+
+    // whether reading the file was stopped by Ctrl-C
+    interrupted: bool,
+}
+
+impl State {
+    fn new(index: usize, file: PathBuf) -> State {
+        State {
+            index,
+            reference: timestamp::reference(&file),
+            file,
+            lines: Vec::new(),
+            keys: HashMap::new(),
+            chunks: Vec::new(),
+            open_chunks: HashMap::new(),
+            range: range::Range::default(),
+            past_range: false,
+            budget: None,
+            footprint: 0,
+            spill: None,
+            interrupted: false,
+        }
+    }
+
+    // Record that `bytes` more of memory are used
+    fn account(&mut self, bytes: usize) {
+        self.footprint += bytes;
+        if let Some(budget) = &self.budget {
+            budget.take(bytes);
+        }
+    }
+
+    fn is_spilled(&self) -> bool {
+        self.spill.is_some()
+    }
+
+    // Whether the memory budget is exceeded and this state holds enough to be
+    // worth moving to disk
+    fn should_spill(&self) -> bool {
+        self.footprint >= SPILL_BATCH
+            && self
+                .budget
+                .as_ref()
+                .map(|b| b.is_exceeded())
+                .unwrap_or(false)
+    }
+
+    // Move all the chunks to disk
+    //
+    // Chunks that are still going on get continued by new chunks, that will be
+    // merged back when the chunks are read back.
+    fn spill(&mut self) -> std::io::Result<()> {
+        if self.spill.is_none() {
+            self.spill = Some(spill::Spill::new()?);
+            self.account(spill::Spill::FOOTPRINT);
+        }
+        let lines = &self.lines;
+        let spill = self.spill.as_mut().expect("just created the spill file");
+        let mut keys = vec![Vec::new(); self.chunks.len()];
+        for (key, chunks) in self.keys.iter() {
+            for &c in chunks {
+                keys[c].push(spill::hash(key));
+            }
+        }
+        for (chunk, keys) in self.chunks.iter().zip(keys) {
+            spill.write(
+                chunk.lines.iter().map(|l| lines[l.line].text.as_str()),
+                spill::hash(&chunk.queue_id.id),
+                chunk
+                    .previous_ids
+                    .iter()
+                    .chain(chunk.next_ids.iter())
+                    .map(|l| spill::hash(&l.id))
+                    .collect(),
+                keys,
+            )?;
+        }
+        spill.flush()?;
+
+        self.lines = Vec::new();
+        self.keys = HashMap::new();
+        self.chunks = Vec::new();
+        self.open_chunks = HashMap::new();
+        // all that is left in memory is the spill itself
+        if let Some(budget) = &self.budget {
+            budget.give_back(self.footprint - spill::Spill::FOOTPRINT);
+        }
+        self.footprint = spill::Spill::FOOTPRINT;
+        Ok(())
+    }
+
+    fn eat(&mut self, line: &[u8]) -> Result<(), ()> {
+        self.push(ParsedLine::parse(line), line)
+    }
+
+    // Record an already-parsed line
+    fn push(&mut self, parsed: ParsedLine, line: &[u8]) -> Result<(), ()> {
+        let this_line = LineRef {
+            file: self.index,
+            line: self.lines.len(),
+        };
+        let time = timestamp::parse(line, self.reference);
+        if !self.range.contains(time) {
+            self.past_range |= self.range.ends_before(time);
+            return Ok(());
+        }
+
+        let is_useless = match parsed {
+            ParsedLine::Postfix { id, details } => {
+                let current = self.open_chunks.get(&id).copied().filter(|&c| {
+                    let last = *self.chunks[c].lines.last().expect("chunk with no lines");
+                    !is_queue_id_gap(self.lines[last.line].time, time)
+                });
+                let c = match current {
+                    Some(c) => c,
+                    None => {
+                        self.account(
+                            std::mem::size_of::<Chunk>()
+                                + 2 * (std::mem::size_of::<QueueId>()
+                                    + id.host.len()
+                                    + id.instance.len()
+                                    + id.id.len()),
+                        );
+                        self.chunks.push(Chunk {
+                            queue_id: id.clone(),
+                            lines: Vec::new(),
+                            previous_ids: Vec::new(),
+                            next_ids: Vec::new(),
+                            removed: false,
+                        });
+                        self.open_chunks.insert(id.clone(), self.chunks.len() - 1);
+                        self.chunks.len() - 1
+                    }
+                };
+                // what the line adds to the chunk and the index
+                let mut bytes = std::mem::size_of::<LineRef>()
+                    + (usize::from(details.previous_id.is_some())
+                        + usize::from(details.next_id.is_some()))
+                        * std::mem::size_of::<Link>();
+                for key in details.keys() {
+                    bytes += std::mem::size_of::<usize>();
+                    if !self.keys.contains_key(&key) {
+                        bytes += key.footprint();
+                    }
+                    self.keys.entry(key).or_default().push(c);
+                }
+                self.account(bytes);
+                let chunk = &mut self.chunks[c];
+                chunk.lines.push(this_line);
+                if let Some(pid) = details.previous_id {
+                    chunk.previous_ids.push(Link {
+                        id: pid,
+                        relay: None,
+                        line: this_line,
+                    });
+                }
+                if let Some(nid) = details.next_id {
+                    chunk.next_ids.push(Link {
+                        id: nid,
+                        relay: details
+                            .delivery
+                            .as_ref()
+                            .and_then(|d| relay_host(&d.relay))
+                            .map(String::from),
+                        line: this_line,
+                    });
+                }
+                if details.removed {
+                    // Any later line with this postfix id is about another mail
+                    chunk.removed = true;
+                    self.open_chunks.remove(&id);
+                }
+                false
+            }
+
+            ParsedLine::Useless => true,
+            ParsedLine::Unknown => return Err(()),
+        };
+
+        if !is_useless {
+            self.account(std::mem::size_of::<Line>() + line.len());
+            self.lines.push(Line {
+                time,
+                // Push the string apart from the trailing \n
+                text: String::from_utf8_lossy(&line[..line.len() - 1]).to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.give_back(self.footprint);
+        }
+    }
+}
+
+// everything we know, merged across all the log files
+struct Logs {
+    // the parsed files, in chronological order
+    states: Vec<State>,
+
+    // message-id, sender, etc. => all the transactions where it appears
+    keys: HashMap<Key, BTreeSet<BlockId>>,
+
+    // transaction => block, with the lines from all the files
+    blocks: HashMap<BlockId, Block>,
+
+    // file => how many of its lines were dropped as copies of an older file
+    duplicates: Vec<usize>,
+}
+
+impl Logs {
+    // Merge the chunks of all the files into one block per transaction
+    //
+    // Chunks of the same postfix id are part of the same transaction if they
+    // follow each other closely and the queue file was not removed in
+    // between, which happens when a transaction spans a log rotation.
+    fn merge(states: Vec<State>, bar: &indicatif::ProgressBar) -> Logs {
+        // a transaction, with the times of its first and last lines
+        type Incarnation = (BlockId, Option<NaiveDateTime>, Option<NaiveDateTime>);
+
+        let time = |l: LineRef| states[l.file].lines[l.line].time;
+
+        // postfix-id => all its chunks, as (file, chunk) indices
+        let mut chunks: HashMap<&QueueId, Vec<(usize, usize)>> = HashMap::new();
+        for s in states.iter() {
+            for (c, chunk) in s.chunks.iter().enumerate() {
+                chunks
+                    .entry(&chunk.queue_id)
+                    .or_default()
+                    .push((s.index, c));
+            }
+        }
+
+        let mut blocks: HashMap<BlockId, Block> = HashMap::new();
+        // (file, chunk) => the transaction it is part of
+        let mut chunk_blocks: HashMap<(usize, usize), BlockId> = HashMap::new();
+        // unqualified postfix-id => the first and last times of each of its
+        // transactions, on all hosts and instances
+        let mut incarnations: HashMap<&str, Vec<Incarnation>> = HashMap::new();
+        let mut accumulated_pos = 0;
+        for (queue_id, mut list) in chunks {
+            // Files are sorted chronologically, but may overlap
+            list.sort_by_key(|&(f, c)| time(states[f].chunks[c].lines[0]));
+
+            let mut spans: Vec<Incarnation> = Vec::new();
+            let mut removed = false;
+            let mut previous_file = None;
+            for (f, c) in list {
+                let chunk = &states[f].chunks[c];
+                let first = time(chunk.lines[0]);
+                let last = time(*chunk.lines.last().expect("chunk with no lines"));
+                let continues = match spans.last() {
+                    Some((_, _, previous_last)) => {
+                        // Another file overlapping the previous chunk is a
+                        // copy of the same lines, written by syslog
+                        let overlaps = previous_file != Some(f)
+                            && matches!((first, previous_last), (Some(first), Some(last)) if first <= *last);
+                        overlaps || (!removed && !is_queue_id_gap(*previous_last, first))
+                    }
+                    None => false,
+                };
+                if !continues {
+                    let id = BlockId {
+                        queue_id: queue_id.clone(),
+                        since: first,
+                        same_second: spans.iter().filter(|(_, f, _)| *f == first).count(),
+                    };
+                    blocks.insert(
+                        id.clone(),
+                        Block {
+                            id: id.clone(),
+                            lines: Vec::new(),
+                            previous_ids: BTreeSet::new(),
+                            next_ids: BTreeSet::new(),
+                        },
+                    );
+                    spans.push((id, first, last));
+                }
+                let span = spans.last_mut().expect("pushed an incarnation just above");
+                span.2 = last.max(span.2);
+                removed = chunk.removed;
+                previous_file = Some(f);
+                blocks
+                    .get_mut(&span.0)
+                    .expect("inserted the block along with the incarnation")
+                    .lines
+                    .extend(chunk.lines.iter().copied());
+                chunk_blocks.insert((f, c), span.0.clone());
+
+                accumulated_pos += 1;
+                if accumulated_pos % (bar.length() / 128).max(1) == 0 {
+                    bar.set_position(accumulated_pos);
+                }
+            }
+            incarnations.entry(&queue_id.id).or_default().extend(spans);
+        }
+        // Syslog often writes the same lines to several files (mail.log,
+        // mail.info, syslog, etc.), so only keep them from the oldest file
+        let mut duplicates = vec![0; states.len()];
+        let key = |l: LineRef| {
+            let line = &states[l.file].lines[l.line];
+            (line.time, timestamp::skip(&line.text))
+        };
+        for b in blocks.values_mut() {
+            b.lines.sort_by_key(|&l| time(l));
+            let mut files: HashMap<_, BTreeSet<usize>> = HashMap::new();
+            for &l in b.lines.iter() {
+                files.entry(key(l)).or_default().insert(l.file);
+            }
+            b.lines.retain(|&l| {
+                let keep = files[&key(l)].iter().next() == Some(&l.file);
+                if !keep {
+                    duplicates[l.file] += 1;
+                }
+                keep
+            });
+        }
+
+        // Find which transaction a link from `from` refers to: the one on the
+        // designated host that was the closest in time to the mention, as long
+        // as it is close enough not to be another mail that was given the same
+        // postfix id
+        let resolve = |from: &QueueId, link: &Link| {
+            let at = time(link.line);
+            let is_on_host = |id: &QueueId| match &link.relay {
+                Some(relay) => is_same_host(&id.host, relay),
+                None => id.host == from.host,
+            };
+            let max_distance = match link.relay {
+                Some(_) => TimeDelta::minutes(RELAY_MAX_SKEW_MINUTES),
+                None => TimeDelta::hours(QUEUE_ID_MAX_GAP_HOURS),
+            };
+            let candidates = incarnations
+                .get(link.id.as_str())
+                .map(|spans| spans.as_slice())
+                .unwrap_or(&[]);
+            candidates
+                .iter()
+                .filter(|(id, _, _)| is_on_host(&id.queue_id))
+                .filter_map(|(id, first, last)| {
+                    let distance = distance_within(at, *first, *last, max_distance)?;
+                    Some((id, (distance, id.queue_id.instance != from.instance)))
+                })
+                .min_by_key(|(_, key)| *key)
+                .map(|(id, _)| id)
+                .or_else(|| {
+                    // The relay may be known under another name than the one
+                    // it logs with, so accept any other host that had the
+                    // postfix id at the very same time
+                    link.relay.as_ref()?;
+                    let max = TimeDelta::minutes(RELAY_MAX_SKEW_MINUTES);
+                    candidates
+                        .iter()
+                        .filter(|(id, _, _)| id.queue_id.host != from.host)
+                        .filter_map(|(id, first, last)| {
+                            Some((id, distance_within(at, *first, *last, max)?))
+                        })
+                        .min_by_key(|(_, distance)| *distance)
+                        .map(|(id, _)| id)
+                })
+                .cloned()
+                // Transactions that are not in the logs (eg. on other hosts)
+                .unwrap_or_else(|| BlockId {
+                    queue_id: QueueId {
+                        host: link.relay.clone().unwrap_or_else(|| from.host.clone()),
+                        instance: match link.relay {
+                            Some(_) => String::from("postfix"),
+                            None => from.instance.clone(),
+                        },
+                        id: link.id.clone(),
+                    },
+                    since: None,
+                    same_second: 0,
+                })
+        };
+
+        // Merge all the keys, next-id's and previous-id's for later use
+        let mut keys: HashMap<Key, BTreeSet<BlockId>> = HashMap::new();
+        let mut edges = Vec::new();
+        for s in states.iter() {
+            for (key, cs) in s.keys.iter() {
+                keys.entry(key.clone())
+                    .or_default()
+                    .extend(cs.iter().map(|&c| chunk_blocks[&(s.index, c)].clone()));
+            }
+            for (c, chunk) in s.chunks.iter().enumerate() {
+                let id = &chunk_blocks[&(s.index, c)];
+                for link in chunk.previous_ids.iter() {
+                    edges.push((resolve(&chunk.queue_id, link), id.clone()));
+                }
+                for link in chunk.next_ids.iter() {
+                    edges.push((id.clone(), resolve(&chunk.queue_id, link)));
+                }
+            }
+        }
+        for (from, to) in edges {
+            if let Some(b) = blocks.get_mut(&from) {
+                b.next_ids.insert(to.clone());
+            }
+            if let Some(b) = blocks.get_mut(&to) {
+                b.previous_ids.insert(from);
+            }
+        }
+
+        Logs {
+            states,
+            keys,
+            blocks,
+            duplicates,
+        }
+    }
+
+    // The transactions with a key
+    //
+    // Postfix ids are not indexed as keys, as they already are in the ids of
+    // the transactions.
+    fn blocks_with(&self, pattern: &search::Pattern) -> Vec<&BlockId> {
+        match pattern.as_key() {
+            Some(Key::QueueId(q)) => self
+                .blocks
+                .keys()
+                .filter(|id| id.queue_id.id == *q)
+                .collect(),
+            Some(key) => self.keys.get(key).into_iter().flatten().collect(),
+            None => self
+                .keys
+                .iter()
+                .filter(|(k, _)| pattern.matches(k))
+                .flat_map(|(_, ids)| ids)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    // The transaction with postfix id `id` on `host` that was going on the
+    // closest to `time`, as long as it is close enough not to be another mail
+    // that was given the same postfix id, or the latest one if `time` is not
+    // known
+    fn incarnation_at(
+        &self,
+        host: &str,
+        id: &str,
+        time: Option<NaiveDateTime>,
+    ) -> Option<&BlockId> {
+        let time_of = |l: Option<&LineRef>| l.and_then(|&l| self.line(l).time);
+        let max = TimeDelta::hours(QUEUE_ID_MAX_GAP_HOURS);
+        self.blocks
+            .values()
+            .filter(|b| b.id.queue_id.id == id && is_same_host(&b.id.queue_id.host, host))
+            .filter_map(|b| {
+                let (first, last) = (time_of(b.lines.first()), time_of(b.lines.last()));
+                Some((&b.id, distance_within(time, first, last, max)?))
+            })
+            .min_by_key(|&(id, distance)| (distance, std::cmp::Reverse(id)))
+            .map(|(id, _)| id)
+    }
+
+    // The postfix ids of all the transactions related to any of the
+    // patterns, directly or through other transactions
+    fn related_queue_ids(&self, patterns: &[search::Pattern]) -> HashSet<String> {
+        let mut todo = patterns
+            .iter()
+            .flat_map(|p| self.blocks_with(p))
+            .cloned()
+            .collect::<Vec<BlockId>>();
+        let mut seen = HashSet::new();
+        while let Some(id) = todo.pop() {
+            if let Some(b) = self.blocks.get(&id) {
+                if !seen.contains(&id) {
+                    todo.extend(b.previous_ids.iter().cloned());
+                    todo.extend(b.next_ids.iter().cloned());
+                }
+            }
+            seen.insert(id);
+        }
+        seen.into_iter().map(|id| id.queue_id.id).collect()
+    }
+
+    fn line(&self, l: LineRef) -> &Line {
+        &self.states[l.file].lines[l.line]
+    }
+}
+
+// The hashes of the postfix id of a chunk in memory and of the ones it links
+// to, as they are kept for the chunks on disk
+fn chunk_hashes(chunk: &Chunk) -> (u64, Vec<u64>) {
+    let links = chunk
+        .previous_ids
+        .iter()
+        .chain(chunk.next_ids.iter())
+        .map(|l| spill::hash(&l.id))
+        .collect();
+    (spill::hash(&chunk.queue_id.id), links)
+}
+
+// Whether a chunk shares postfix ids with the related ones
+fn shares_ids(ids: &HashSet<u64>, queue_id: u64, links: &[u64]) -> bool {
+    ids.contains(&queue_id) || links.iter().any(|l| ids.contains(l))
+}
+
+// Rebuild the states with only the chunks that may be related to the
+// patterns, reading them back from disk for the states that were moved there
+//
+// Chunks are related if they share postfix ids with related chunks, which is
+// looser than how `Logs::merge` links them but needs much less memory. Only the
+// hashes of the keys of the chunks on disk are known, so they must be read back
+// to be matched against patterns that are not just a key, or against the
+// predicates of a query, that are found the same way. The chunks on disk are
+// only indexed on disk too, so the postfix ids are walked by going through
+// that index again for each hop, keeping only the related ids in memory.
+fn related_states(
+    states: &[State],
+    patterns: &[search::Pattern],
+    query: Option<&query::Query>,
+) -> anyhow::Result<Vec<State>> {
+    let queue_ids = patterns
+        .iter()
+        .filter_map(|p| match p.as_key() {
+            Some(Key::QueueId(q)) => Some(spill::hash(q)),
+            _ => None,
+        })
+        .collect::<HashSet<u64>>();
+    let keys = patterns
+        .iter()
+        .filter_map(|p| p.as_key())
+        .map(spill::hash)
+        .collect::<HashSet<u64>>();
+    // a query that may hold for mails matching none of its predicates may
+    // hold for any chunk
+    let anchors = query.map(|q| q.anchors());
+    let everything = matches!(anchors, Some(None));
+    let must_read_back = query.is_some() || patterns.iter().any(|p| p.as_key().is_none());
+    let matches = |lines: &[u8]| {
+        let lines = || lines.split_inclusive(|&c| c == b'\n');
+        let matches_pattern = |line: &[u8]| match ParsedLine::parse(line) {
+            ParsedLine::Postfix { details, .. } => details
+                .keys()
+                .iter()
+                .any(|k| patterns.iter().any(|p| p.matches(k))),
+            _ => false,
+        };
+        lines().any(matches_pattern)
+            || matches!(&anchors, Some(Some(anchors)) if query::any_holds(anchors, lines()))
+    };
+    let reading_back = |s: &State| format!("reading back log file {:?}", s.file);
+
+    // The postfix ids of the chunks that match
+    let mut ids = HashSet::new();
+    for s in states.iter() {
+        for chunk in s.spill.iter().flat_map(|sp| sp.chunks()) {
+            let chunk = chunk.with_context(|| reading_back(s))?;
+            let mut is_related = everything
+                || queue_ids.contains(&chunk.queue_id)
+                || chunk.keys.iter().any(|k| keys.contains(k));
+            if !is_related && must_read_back {
+                let spill = s.spill.as_ref().expect("spilled chunk with no spill");
+                let lines = spill.read(&chunk).with_context(|| reading_back(s))?;
+                is_related = matches(&lines);
+            }
+            if is_related {
+                ids.insert(chunk.queue_id);
+                ids.extend(chunk.links);
+            }
+        }
+        let seeds = s
+            .keys
+            .iter()
+            .filter(|(k, _)| patterns.iter().any(|p| p.matches(k)))
+            .flat_map(|(_, cs)| cs.iter().copied())
+            .collect::<HashSet<usize>>();
+        for (c, chunk) in s.chunks.iter().enumerate() {
+            let (queue_id, links) = chunk_hashes(chunk);
+            let is_related = everything
+                || seeds.contains(&c)
+                || queue_ids.contains(&queue_id)
+                || (query.is_some() && {
+                    let lines = chunk
+                        .lines
+                        .iter()
+                        .map(|l| format!("{}\n", s.lines[l.line].text))
+                        .collect::<String>();
+                    matches(lines.as_bytes())
+                });
+            if is_related {
+                ids.insert(queue_id);
+                ids.extend(links);
+            }
+        }
+    }
+
+    // Then the ones of the chunks that share postfix ids with them, until
+    // there are no more
+    loop {
+        let count = ids.len();
+        for s in states.iter() {
+            for chunk in s.spill.iter().flat_map(|sp| sp.chunks()) {
+                let chunk = chunk.with_context(|| reading_back(s))?;
+                if shares_ids(&ids, chunk.queue_id, &chunk.links) {
+                    ids.insert(chunk.queue_id);
+                    ids.extend(chunk.links);
+                }
+            }
+            for chunk in s.chunks.iter() {
+                let (queue_id, links) = chunk_hashes(chunk);
+                if shares_ids(&ids, queue_id, &links) {
+                    ids.insert(queue_id);
+                    ids.extend(links);
+                }
+            }
+        }
+        if ids.len() == count {
+            break;
+        }
+    }
+
+    // Parse the related chunks again, in the order they were first parsed,
+    // within the same budget
+    let mut res = Vec::with_capacity(states.len());
+    for s in states.iter() {
+        let mut state = State::new(s.index, s.file.clone());
+        state.range = s.range;
+        state.budget = s.budget.clone();
+        // Lines were already parsed once, so they are known to be fine
+        for chunk in s.spill.iter().flat_map(|sp| sp.chunks()) {
+            let chunk = chunk.with_context(|| reading_back(s))?;
+            if shares_ids(&ids, chunk.queue_id, &chunk.links) {
+                let spill = s.spill.as_ref().expect("spilled chunk with no spill");
+                let lines = spill.read(&chunk).with_context(|| reading_back(s))?;
+                for line in lines.split_inclusive(|&c| c == b'\n') {
+                    let _ = state.eat(line);
+                }
+            }
+        }
+        for chunk in s.chunks.iter() {
+            let (queue_id, links) = chunk_hashes(chunk);
+            if shares_ids(&ids, queue_id, &links) {
+                for l in chunk.lines.iter() {
+                    let _ = state.eat(format!("{}\n", s.lines[l.line].text).as_bytes());
+                }
+            }
+        }
+        res.push(state);
+    }
+    Ok(res)
+}
+
+fn run(mut opt: Opt) -> anyhow::Result<()> {
+    let has_search_flag = opt.from.is_some()
+        || opt.to.is_some()
+        || opt.queue_id.is_some()
+        || opt.client.is_some()
+        || opt.sasl_user.is_some()
+        || opt.query.is_some()
+        || opt.eml.is_some();
+    // Arguments that are taken as message-ids rather than as files
+    let is_message_id = |f: &Path| {
+        let arg = f.to_string_lossy();
+        !input::is_stdin(f)
+            && !f.exists()
+            && (arg.contains('@')
+                || arg.to_ascii_lowercase().contains("%40")
+                || Key::looks_like_queue_id(&arg))
+    };
+    // With something else to look for, what looks like a message-id is the
+    // first file, and so is it along with --ids-from if it is not one
+    let is_file = match &opt.message_id {
+        Some(arg) => has_search_flag || (opt.ids_from.is_some() && !is_message_id(Path::new(arg))),
+        None => false,
+    };
+    if is_file {
+        if let Some(file) = opt.message_id.take() {
+            opt.files.insert(0, PathBuf::from(file));
+        }
+    }
+
+    ensure!(
+        opt.files
+            .iter()
+            .chain(opt.eml.iter())
+            .filter(|f| input::is_stdin(f))
+            .count()
+            <= 1,
+        "The standard input (‘{}’) can only be read once",
+        input::STDIN,
+    );
+
+    // Message-ids may be followed by more of them, up to the first file
+    let mut message_ids = opt.message_id.iter().cloned().collect::<Vec<String>>();
+    if !message_ids.is_empty() {
+        let more = opt.files.iter().take_while(|f| is_message_id(f)).count();
+        message_ids.extend(
+            opt.files
+                .drain(..more)
+                .map(|f| f.to_string_lossy().into_owned()),
+        );
+    }
+    if let Some(ids_from) = &opt.ids_from {
+        let ids = std::fs::read_to_string(ids_from)
+            .with_context(|| format!("reading message-ids from {:?}", ids_from))?;
+        message_ids.extend(
+            ids.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(String::from),
+        );
+        ensure!(
+            !message_ids.is_empty(),
+            "No message-id found in {:?}",
+            ids_from
+        );
+    }
+    let mut seen = HashSet::new();
+    message_ids.retain(|m| seen.insert(m.clone()));
+
+    if opt.follow {
+        if opt.files.is_empty() {
+            opt.files = vec![PathBuf::from(DEFAULT_FOLLOW_LOCATION)];
+        }
+        let timeout = Duration::from_secs(opt.timeout.unwrap_or(DEFAULT_FOLLOW_TIMEOUT));
+        ensure!(
+            message_ids.len() == 1,
+            "Only one message-id can be followed at a time"
+        );
+        let (mid, _) = message_id::normalize(&message_ids[0]);
+        return follow::follow(&mid, &opt.files, timeout);
+    }
+
+    if let Err(e) = interrupt::install() {
+        eprintln!(
+            "{}: unable to catch Ctrl-C, hitting it will lose everything: {}",
+            style("warning").bold().yellow(),
+            e
+        );
+    }
+
+    // Recover the file list
+    if opt.files.is_empty() {
+        opt.files = glob::glob(DEFAULT_LOG_LOCATION)
+            .context("grepping for log files")?
+            .collect::<Result<Vec<_>, _>>()
+            .context("grepping for log files")?;
+        ensure!(
+            !opt.files.is_empty(),
+            "No log file found at {}",
+            DEFAULT_LOG_LOCATION
+        );
+    }
+    if opt.rotated {
+        let mut rotated = Vec::new();
+        for file in opt.files.iter().filter(|f| !input::is_stdin(f)) {
+            rotated.extend(
+                discover::rotated_versions(file)
+                    .with_context(|| format!("looking for rotated versions of {:?}", file))?,
+            );
+        }
+        opt.files.extend(rotated);
+        let mut seen = HashSet::new();
+        opt.files.retain(|f| seen.insert(f.clone()));
+    }
+
+    // Read the summaries of the files, if asked to, before anything else
+    // opens the files
+    let mut summaries = if opt.summaries {
+        opt.files
+            .par_iter()
+            .filter_map(|f| Some((f.clone(), summary::Summary::read(f)?)))
+            .collect::<HashMap<_, _>>()
+    } else {
+        HashMap::new()
+    };
+    discover::sort_chronologically(&mut opt.files, |f| summaries.get(f).and_then(|s| s.first));
+
+    ensure!(
+        match (opt.since, opt.until) {
+            (Some(since), Some(until)) => since <= until,
+            _ => true,
+        },
+        "--since must be before --until"
+    );
+    let range = range::Range {
+        start: opt
+            .since
+            .map(|t| t.checked_sub_signed(opt.margin))
+            .map(|t| t.context("--margin is too long for --since"))
+            .transpose()?,
+        end: opt
+            .until
+            .map(|t| t.checked_add_signed(opt.margin))
+            .map(|t| t.context("--margin is too long for --until"))
+            .transpose()?,
+    };
+
+    let files = opt
+        .files
+        .iter()
+        .map(|f| (f.clone(), summaries.remove(f)))
+        .collect::<Vec<_>>();
+
+    // Skip the files that are entirely out of the requested time range
+    let count = files.len();
+    let (files, summaries): (Vec<PathBuf>, Vec<Option<summary::Summary>>) = files
+        .into_iter()
+        .filter(|(f, summary)| match summary {
+            Some(s) => range.overlaps(s.first, s.last),
+            None => range.may_overlap(f),
+        })
+        .unzip();
+    if files.len() < count {
+        eprintln!(
+            "skipping {} out of {} log file(s) that are out of the requested time range",
+            count - files.len(),
+            count,
+        );
+    }
+    ensure!(
+        !files.is_empty(),
+        "No log file covers the requested time range"
+    );
+
+    // What to look for, as patterns to try one after the other, message-ids
+    // being logged with or without brackets
+    let matching = if opt.substring {
+        Some(search::Matching::Substring)
+    } else if opt.glob {
+        Some(search::Matching::Glob)
+    } else if opt.regex {
+        Some(search::Matching::Regex)
+    } else {
+        None
+    };
+    // each with the name it is reported by, when there are several
+    let eml = opt.eml.as_deref().map(eml::Eml::read).transpose()?;
+    let searches = if opt.query.is_some() {
+        // which is looked for once everything is loaded
+        Vec::new()
+    } else if let (Some(eml), Some(file)) = (&eml, &opt.eml) {
+        ensure!(
+            !eml.message_ids.is_empty() || !eml.received.is_empty(),
+            "found neither a message-id nor a postfix id in the headers of {:?}",
+            file
+        );
+        let mut patterns = Vec::new();
+        for mid in eml.message_ids.iter() {
+            eprintln!(
+                "{}: the mail has message-id ‘{}’",
+                style("note").bold().cyan(),
+                mid
+            );
+            patterns.push(Key::MessageId(mid.clone()));
+            patterns.push(Key::MessageId(format!("<{}>", mid)));
+        }
+        for r in eml.received.iter() {
+            eprintln!(
+                "{}: the mail went through {} with id ‘{}’",
+                style("note").bold().cyan(),
+                r.host,
+                r.id
+            );
+            // only to know which files to load, see below
+            patterns.push(Key::QueueId(r.id.clone()));
+        }
+        let patterns = patterns.into_iter().map(search::Pattern::Exact).collect();
+        vec![(format!("{:?}", file), patterns)]
+    } else if !message_ids.is_empty() {
+        message_ids
+            .iter()
+            .map(|mid| Ok((mid.clone(), message_id_attempts(mid, matching)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?
+    } else if let (Some(from), Some(to)) = (&opt.from, &opt.to) {
+        ensure!(
+            matching.is_none(),
+            "--substring, --glob and --regex cannot be used with both --from and --to, see --query instead"
+        );
+        let patterns = vec![
+            search::Pattern::Exact(Key::sender(from)),
+            search::Pattern::Exact(Key::recipient(to)),
+        ];
+        vec![(format!("{} and {}", patterns[0], patterns[1]), patterns)]
+    } else {
+        let pattern = if let (Some(matching), Some(from)) = (matching, &opt.from) {
+            let field = search::Field::Sender;
+            search::Pattern::Text(field, search::Matcher::new(matching, field, from)?)
+        } else if let (Some(matching), Some(to)) = (matching, &opt.to) {
+            let field = search::Field::Recipient;
+            search::Pattern::Text(field, search::Matcher::new(matching, field, to)?)
+        } else if let Some(from) = &opt.from {
+            search::Pattern::Exact(Key::sender(from))
+        } else if let Some(to) = &opt.to {
+            search::Pattern::Exact(Key::recipient(to))
+        } else if let Some(id) = &opt.queue_id {
+            search::Pattern::Exact(Key::QueueId(id.clone()))
+        } else if let Some(client) = &opt.client {
+            client.clone()
+        } else if let Some(user) = &opt.sasl_user {
+            search::Pattern::Exact(Key::SaslUser(user.clone()))
+        } else {
+            unreachable!("structopt requires something to look for")
+        };
+        vec![(pattern.to_string(), vec![pattern])]
+    };
+    let patterns = searches
+        .iter()
+        .flat_map(|(_, attempts)| attempts.iter().cloned())
+        .collect::<Vec<_>>();
+
+    // Clients may have sent many mails, that are better listed than drawn
+    let summarize_above = if opt.client.is_some() || opt.sasl_user.is_some() {
+        Some(MAX_DRAWN_MAILS)
+    } else {
+        None
+    };
+    // and patterns may match much more than intended
+    let cap = matching.map(|_| MAX_MATCHED_MAILS);
 
-    // message-id => all the postfix-id's where it appears
-    message_ids: HashMap<String, Vec<String>>,
+    // Only load the files that may be about the message, then the ones that
+    // may hold the rest of its transactions, until there are no more
+    let budget = opt.memory_limit.map(|l| Arc::new(spill::Budget::new(l)));
+    let mut states = files
+        .iter()
+        .enumerate()
+        .map(|(index, file)| {
+            let mut state = State::new(index, file.clone());
+            state.range = range;
+            state.budget = budget.clone();
+            state
+        })
+        .collect::<Vec<_>>();
+    let mut loaded = vec![false; files.len()];
+    let mut unread = Vec::new();
+    let mut to_load = summaries
+        .iter()
+        .enumerate()
+        .filter(|(_, summary)| match summary {
+            Some(s) => opt.query.is_some() || patterns.iter().any(|p| p.may_be_in(s)),
+            None => true,
+        })
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    let logs = loop {
+        load_all(
+            &mut states,
+            &to_load,
+            opt.fail_fast,
+            opt.summaries,
+            opt.mmap,
+        )?;
+        for &i in to_load.iter() {
+            loaded[i] = true;
+        }
+        let interrupted = interrupt::is_interrupted();
+        if interrupted {
+            unread.extend(states.iter().filter(|s| s.interrupted).map(|s| {
+                let read_some = !s.lines.is_empty() || s.is_spilled();
+                (s.file.clone(), read_some)
+            }));
+        }
 
-    // postfix-id => block
-    blocks: HashMap<String, Block>,
-}
+        // Merge all the files together
+        let bar = indicatif::ProgressBar::new(states.iter().map(|s| s.chunks.len() as u64).sum());
+        bar.set_style(bar_style());
+        bar.set_prefix("merging all data together");
+        let (logs, kept) = if states.iter().any(State::is_spilled) {
+            if opt.query.as_ref().is_some_and(|q| q.anchors().is_none()) {
+                eprintln!(
+                    "{}: the query may hold for mails that match none of its predicates, so all the log files moved to disk are read back",
+                    style("warning").bold().yellow(),
+                );
+            }
+            // Only read back what may be related to the message
+            let related = related_states(&states, &patterns, opt.query.as_ref())
+                .context("reading back the log files moved to disk")?;
+            (Logs::merge(related, &bar), Some(states))
+        } else {
+            (Logs::merge(states, &bar), None)
+        };
+        bar.finish();
 
-impl State {
-    fn new(file: PathBuf) -> State {
-        State {
-            file,
-            lines: Vec::new(),
-            message_ids: HashMap::new(),
-            blocks: HashMap::new(),
+        let queue_ids = logs.related_queue_ids(&patterns);
+        to_load = (0..files.len())
+            .filter(|&i| !loaded[i])
+            .filter(|&i| match &summaries[i] {
+                Some(s) => queue_ids.iter().any(|id| s.may_have_queue_id(id)),
+                None => false,
+            })
+            .collect();
+        if interrupted {
+            unread.extend(to_load.iter().map(|&i| (files[i].clone(), false)));
+            break logs;
+        }
+        if to_load.is_empty() {
+            break logs;
+        }
+        states = kept.unwrap_or(logs.states);
+    };
+    let skipped = loaded.iter().filter(|&&l| !l).count();
+    if skipped > 0 {
+        eprintln!(
+            "skipped {} out of {} log file(s) that their summary tells are not about the message",
+            skipped,
+            files.len(),
+        );
+    }
+    for (s, &duplicates) in logs.states.iter().zip(logs.duplicates.iter()) {
+        if !s.lines.is_empty() && duplicates == s.lines.len() {
+            eprintln!(
+                "{}: all the lines of {:?} also are in older files, it can be skipped",
+                style("note").bold().cyan(),
+                s.file,
+            );
         }
     }
 
-    fn eat(&mut self, line: &[u8]) -> Result<(), ()> {
-        let this_line = self.lines.len();
-        let parsed = ParsedLine::parse(line);
-
-        let is_useless = match parsed {
-            ParsedLine::Postfix {
-                id,
-                message_id,
-                previous_id,
-                next_id,
-            } => {
-                if let Some(mid) = message_id {
-                    self.message_ids
-                        .entry(mid)
-                        .or_insert_with(Vec::new)
-                        .push(id.clone());
-                }
-                let block = {
-                    let file = &self.file;
-                    self.blocks.entry(id.clone()).or_insert_with(|| Block {
-                        id,
-                        file: file.clone(),
-                        lines: Vec::new(),
-                        previous_ids: HashSet::new(),
-                        next_ids: HashSet::new(),
-                    })
-                };
-                block.lines.push(this_line);
-                if let Some(pid) = previous_id {
-                    block.previous_ids.insert(pid);
-                }
-                if let Some(nid) = next_id {
-                    block.next_ids.insert(nid);
-                }
-                false
+    if !unread.is_empty() {
+        eprintln!(
+            "{}: interrupted, only showing what was found in what could be read so far; not (fully) read:",
+            style("warning").bold().yellow(),
+        );
+        for (file, read_some) in unread {
+            if read_some {
+                eprintln!("  {:?}: partially read", file);
+            } else {
+                eprintln!("  {:?}: not read", file);
             }
+        }
+    }
 
-            ParsedLine::Useless => true,
-            ParsedLine::Unknown => return Err(()),
+    // Display the result
+    if let Some(query) = &opt.query {
+        let blocks = query::matching(query, &logs);
+        let found = draw(
+            blocks,
+            &HashSet::new(),
+            &logs,
+            Some(MAX_DRAWN_MAILS),
+            Some(MAX_MATCHED_MAILS),
+        )?;
+        ensure!(found > 0, "found no mail matching the query");
+        return Ok(());
+    }
+    if let (Some(from), Some(to), [(name, _)]) = (&opt.from, &opt.to, &searches[..]) {
+        // which may well be in different transactions of the mail
+        let query = query::Query::between(from, to, opt.since, opt.until);
+        let to = normalize_address(to);
+        let found = draw(
+            query::matching(&query, &logs),
+            &std::iter::once(&to).collect(),
+            &logs,
+            None,
+            None,
+        )
+        .context("displaying the result")?;
+        ensure!(found > 0, "found no mail with {}", name);
+        return Ok(());
+    }
+    if let (Some(eml), [(name, _)]) = (&eml, &searches[..]) {
+        // A postfix id only designates a transaction on the host that gave
+        // it, at the time it did, as it gets reused over time
+        let mut blocks = eml
+            .message_ids
+            .iter()
+            .flat_map(|mid| {
+                [
+                    Key::MessageId(mid.clone()),
+                    Key::MessageId(format!("<{}>", mid)),
+                ]
+            })
+            .flat_map(|key| logs.blocks_with(&search::Pattern::Exact(key)))
+            .collect::<BTreeSet<&BlockId>>();
+        blocks.extend(
+            eml.received
+                .iter()
+                .filter_map(|r| logs.incarnation_at(&r.host, &r.id, r.time)),
+        );
+        let blocks = blocks
+            .into_iter()
+            .filter_map(|id| logs.blocks.get(id))
+            .collect::<Vec<&Block>>();
+        let found =
+            draw(blocks, &HashSet::new(), &logs, None, None).context("displaying the result")?;
+        ensure!(found > 0, "found no mail from {} in the logs", name);
+        return Ok(());
+    }
+    // with a table of what was found when looking for a list of message-ids
+    if let ([(_, attempts)], None) = (&searches[..], &opt.ids_from) {
+        if display_first(attempts, &logs, summarize_above, cap)? > 0 {
+            return Ok(());
+        }
+        match &attempts[..] {
+            [pattern] => bail!("found no mail with {}", pattern),
+            _ => bail!(
+                "found no mail with any of {}",
+                attempts
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+    let mut found = Vec::with_capacity(searches.len());
+    for (name, attempts) in searches.iter() {
+        println!();
+        println!("{} {}", style("══").bold(), style(name).bold());
+        found.push(display_first(attempts, &logs, summarize_above, cap)?);
+    }
+
+    // and what was found for each
+    println!();
+    let width = searches
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for ((name, _), &count) in searches.iter().zip(found.iter()) {
+        let result = if count > 0 {
+            style(format!("found ({} transaction(s))", count)).green()
+        } else {
+            style(String::from("not found")).red()
         };
+        println!("{:width$}  {}", name, result, width = width);
+    }
+    ensure!(
+        found.iter().any(|&count| count > 0),
+        "found none of the {} message-ids",
+        searches.len()
+    );
+    Ok(())
+}
 
-        if !is_useless {
-            // Push the string apart from the trailing \n
-            self.lines
-                .push(String::from_utf8_lossy(&line[..line.len() - 1]).to_string());
+// The patterns to try one after the other to find a message-id, which may
+// have been pasted with extra characters, be logged with or without brackets,
+// or actually be a postfix id
+fn message_id_attempts(
+    arg: &str,
+    matching: Option<search::Matching>,
+) -> anyhow::Result<Vec<search::Pattern>> {
+    // regexes are taken as they are, as cleaning them up would change them
+    let (mid, steps) = match matching {
+        Some(search::Matching::Regex) => (arg.to_string(), Vec::new()),
+        _ => message_id::normalize(arg),
+    };
+    let bracketed = steps.contains(&"angle brackets stripped");
+    // brackets alone are nothing worth telling about
+    if steps.len() > usize::from(bracketed) {
+        eprintln!(
+            "{}: looking for ‘{}’ as ‘{}’ ({})",
+            style("note").bold().cyan(),
+            arg,
+            mid,
+            steps.join(", "),
+        );
+    }
+    // the cleaned up message-id, then as it was typed, in case its %XX were
+    // really part of it
+    let mut forms = vec![mid.clone()];
+    if steps.contains(&"URL-decoded") {
+        forms.push(message_id::normalize_undecoded(arg));
+    }
+    if let Some(matching) = matching {
+        let field = search::Field::MessageId;
+        return forms
+            .iter()
+            .map(|form| {
+                Ok(search::Pattern::Text(
+                    field,
+                    search::Matcher::new(matching, field, form)?,
+                ))
+            })
+            .collect();
+    }
+    let mut attempts = Vec::new();
+    for form in forms {
+        // the form it was given in first
+        let mut both = [
+            Key::MessageId(String::from("<") + &form + ">"),
+            Key::MessageId(form),
+        ];
+        if !bracketed {
+            both.reverse();
         }
-        Ok(())
+        attempts.extend(both);
+    }
+    if Key::looks_like_queue_id(&mid) {
+        attempts.insert(0, Key::QueueId(mid));
     }
+    Ok(attempts.into_iter().map(search::Pattern::Exact).collect())
 }
 
-fn run(mut opt: Opt) -> anyhow::Result<()> {
-    // Recover the file list
-    if opt.files.is_empty() {
-        opt.files = glob::glob(DEFAULT_LOG_LOCATION)
-            .context("grepping for log files")?
-            .into_iter()
-            .collect::<Result<Vec<_>, _>>()
-            .context("grepping for log files")?;
-        ensure!(
-            !opt.files.is_empty(),
-            "No log file found at {}",
-            DEFAULT_LOG_LOCATION
-        );
+// Display the transactions found with the first of the patterns that finds
+// any, returning how many were found
+fn display_first(
+    attempts: &[search::Pattern],
+    logs: &Logs,
+    summarize_above: Option<usize>,
+    cap: Option<usize>,
+) -> anyhow::Result<usize> {
+    for (i, pattern) in attempts.iter().enumerate() {
+        let found = display(std::slice::from_ref(pattern), logs, summarize_above, cap)
+            .context("displaying the result")?;
+        if found > 0 {
+            if i > 0 {
+                eprintln!(
+                    "{}: found the mail with {}",
+                    style("note").bold().cyan(),
+                    pattern
+                );
+            }
+            return Ok(found);
+        }
+        if let Some(next) = attempts.get(i + 1) {
+            eprintln!(
+                "{}: found no mail with {}, trying with {}",
+                style("warning").bold().yellow(),
+                pattern,
+                next,
+            );
+        }
     }
+    Ok(0)
+}
 
-    // Prepare the progress bars
-    let bar_style = indicatif::ProgressStyle::default_bar().template(
+fn bar_style() -> indicatif::ProgressStyle {
+    indicatif::ProgressStyle::default_bar().template(
         "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes:>8}/{total_bytes:8} ({eta}) {prefix}  {wide_msg}",
-    ).progress_chars("=>-");
+    ).progress_chars("=>-")
+}
+
+// Load the files of the `states` listed in `which` in parallel, keeping
+// whatever could be read from the damaged ones unless `fail_fast` is set,
+// writing their summaries if `summarize` is set and memory-mapping the
+// uncompressed ones if `mmap` is set
+fn load_all(
+    states: &mut [State],
+    which: &[usize],
+    fail_fast: bool,
+    summarize: bool,
+    mmap: bool,
+) -> anyhow::Result<()> {
+    if which.is_empty() {
+        return Ok(());
+    }
+
+    // Prepare the progress bars
+    let spinner_style = indicatif::ProgressStyle::default_spinner()
+        .template("{spinner:.green} [{elapsed_precise}] {bytes:>8} {prefix}  {wide_msg}");
     let multi_progress = indicatif::MultiProgress::new();
-    let max_filename_len = opt
-        .files
+    let max_filename_len = which
         .iter()
-        .map(|f| format!("{:?}", f).len())
+        .map(|&i| format!("{:?}", states[i].file).len())
         .max()
         .expect("failed to find max of verified-non-empty list");
-    let bars = opt
-        .files
-        .iter()
-        .map(|file| {
-            let size = std::fs::metadata(file)
-                .with_context(|| format!("retrieving metadata for log file {:?}", file))?
-                .len();
-            let bar = multi_progress.add(indicatif::ProgressBar::new(size));
-            bar.set_style(bar_style.clone());
-            bar.set_prefix(&format!(
-                "loading {name:width$?}",
-                name = file,
-                width = max_filename_len,
-            ));
-            Ok(bar)
-        })
-        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut bars = Vec::with_capacity(states.len());
+    for (i, state) in states.iter().enumerate() {
+        if !which.contains(&i) {
+            bars.push(None);
+            continue;
+        }
+        let file = &state.file;
+        let size = match input::size(file) {
+            Ok(size) => size,
+            Err(e) if fail_fast => {
+                return Err(e)
+                    .with_context(|| format!("retrieving metadata for log file {:?}", file))
+            }
+            // The error will show up again when opening the file
+            Err(_) => None,
+        };
+        let bar = match size {
+            Some(size) => {
+                let bar = multi_progress.add(indicatif::ProgressBar::new(size));
+                bar.set_style(bar_style());
+                bar
+            }
+            None => {
+                // We cannot know in advance how much will be read
+                let bar = multi_progress.add(indicatif::ProgressBar::new_spinner());
+                bar.set_style(spinner_style.clone());
+                bar
+            }
+        };
+        bar.set_prefix(&format!(
+            "loading {name:width$?}",
+            name = file,
+            width = max_filename_len,
+        ));
+        bars.push(Some(bar));
+    }
     std::thread::spawn(move || {
         multi_progress
             .join()
             .expect("failed joining the progress bars");
     });
 
-    // Parse the files
-    let mut states = opt
-        .files
-        .iter()
-        .zip(bars.into_iter())
-        .par_bridge()
-        .map(|(file, bar)| {
-            let f = std::fs::File::open(file)
-                .with_context(|| format!("opening log file {:?}", file))?;
-            let f = std::io::BufReader::new(bar.wrap_read(f));
-            let mut f = if file.extension().and_then(|e| e.to_str()) == Some("gz") {
-                Box::new(std::io::BufReader::new(
-                    libflate::gzip::Decoder::new(f)
-                        .with_context(|| format!("considering log file {:?} as gzipped", file))?,
-                )) as Box<dyn 'static + BufRead>
-            } else {
-                Box::new(f)
-            };
-
-            let mut state = State::new(file.clone());
-            let mut showed_message = false;
-            let mut lineno = 0;
-            let mut l = Vec::new();
-            loop {
-                // Read the line
-                lineno += 1;
-                l.truncate(0);
-                let read = f
-                    .read_until(b'\n', &mut l)
-                    .with_context(|| format!("reading file {:?}", file))?;
-                if read == 0 {
-                    break;
+    // Parse the files, keeping whatever could be read from the damaged ones
+    let damaged = states
+        .par_iter_mut()
+        .zip(bars)
+        .filter_map(|(state, bar)| bar.map(|bar| (state, bar)))
+        .map(|(state, bar)| {
+            let stamp = summary::Stamp::of(&state.file);
+            let res = load(state, &bar, mmap);
+            if let Err(e) = &res {
+                bar.set_message(&format!("{}: {:#}", style("warning").bold().yellow(), e));
+            }
+            match res {
+                Err(e) if fail_fast => return Err(e),
+                Err(e) => {
+                    bar.finish();
+                    let read_some = !state.lines.is_empty() || state.is_spilled();
+                    return Ok(Some((state.file.clone(), read_some, e)));
                 }
-
-                // Parse the line
-                if state.eat(&l).is_err() && !showed_message {
-                    bar.set_message(&format!(
-                        "{}: unable to parse line {}: {}",
-                        style("warning").bold().yellow(),
-                        lineno,
-                        String::from_utf8_lossy(&l),
-                    ));
-                    showed_message = true;
+                Ok(()) => (),
+            }
+            if state.interrupted {
+                bar.set_message("interrupted");
+            }
+            // Partially-loaded files and streams must not be summarized
+            let is_complete = state.range.is_unbounded()
+                && !state.is_spilled()
+                && !state.interrupted
+                && input::is_regular(&state.file);
+            if let (true, Ok(stamp), true) = (summarize, stamp, is_complete) {
+                if let Err(e) = summary::Summary::build(stamp, state).write(&state.file) {
+                    bar.set_message(&format!("{}: {:#}", style("warning").bold().yellow(), e));
                 }
             }
             bar.finish();
-            Ok((file.clone(), state))
+            Ok(None)
         })
-        .collect::<anyhow::Result<HashMap<PathBuf, State>>>()?;
-
-    // Merge all the next-id and previous-id for later use
-    let bar = indicatif::ProgressBar::new(states.iter().map(|(_, s)| s.blocks.len() as u64).sum());
-    bar.set_style(bar_style);
-    bar.set_prefix("merging all data together");
-    let mut accumulated_pos = 0;
-    let mut points_to: HashMap<String, HashSet<String>> = HashMap::new();
-    let mut pointed_by: HashMap<String, HashSet<String>> = HashMap::new();
-    for (_, s) in states.iter() {
-        for (id, b) in s.blocks.iter() {
-            points_to
-                .entry(id.clone())
-                .or_insert_with(HashSet::new)
-                .extend(b.next_ids.iter().cloned());
-            for pid in b.previous_ids.iter() {
-                points_to
-                    .entry(pid.clone())
-                    .or_insert_with(HashSet::new)
-                    .insert(id.clone());
-            }
-            pointed_by
-                .entry(id.clone())
-                .or_insert_with(HashSet::new)
-                .extend(b.previous_ids.iter().cloned());
-            for nid in b.next_ids.iter() {
-                pointed_by
-                    .entry(nid.clone())
-                    .or_insert_with(HashSet::new)
-                    .insert(id.clone());
-            }
-            accumulated_pos += 1;
-            if accumulated_pos % (bar.length() / 128) == 0 {
-                bar.set_position(accumulated_pos);
-            }
-        }
-    }
-    for (_, s) in states.iter_mut() {
-        for (id, b) in s.blocks.iter_mut() {
-            b.previous_ids = pointed_by
-                .get(id)
-                .expect("got block with no pointed-by list")
-                .clone();
-            b.next_ids = points_to
-                .get(id)
-                .expect("got block with no points-to list")
-                .clone();
-        }
-    }
-    bar.finish();
-
-    // Display the result
-    if !display(&opt.message_id, states.clone()).context("displaying the result")? {
+        .collect::<anyhow::Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    if !damaged.is_empty() {
         eprintln!(
-            "{}: found no mail with the requested message-id, trying with ‘<{}>’",
+            "{}: {} log file(s) could not be fully read, the result may be incomplete:",
             style("warning").bold().yellow(),
-            opt.message_id
+            damaged.len(),
         );
-        let bracketed_mid = String::from("<") + &opt.message_id + ">";
-        if !display(&bracketed_mid, states).context("displaying the result")? {
-            bail!("found logs for neither ‘{0}’ nor ‘<{0}>’", opt.message_id);
+        for (file, read_some, e) in damaged {
+            if !read_some {
+                eprintln!("  {:?}: skipped ({:#})", file, e);
+            } else {
+                eprintln!("  {:?}: partially read ({:#})", file, e);
+            }
         }
     }
-
     Ok(())
 }
 
-fn display(message_id: &str, states: HashMap<PathBuf, State>) -> anyhow::Result<bool> {
-    // Search the states for the blocks that are relevant to the message-id
-    let blocks = states
+// Read a log file into `state`, which keeps the lines read before any error
+//
+// Uncompressed files are memory-mapped if `mmap` is set, so that lines get
+// parsed right from the map instead of being copied first.
+fn load(state: &mut State, bar: &indicatif::ProgressBar, mmap: bool) -> anyhow::Result<()> {
+    if interrupt::is_interrupted() {
+        state.interrupted = true;
+        return Ok(());
+    }
+    let file = state.file.clone();
+    let seekable = (mmap || state.range.start.is_some())
+        && input::is_seekable_plain(&file)
+            .with_context(|| format!("opening log file {:?}", file))?;
+    let mut offset = 0;
+    if let (true, Some(start)) = (seekable, state.range.start) {
+        // Jump right to the first interesting line
+        offset = range::seek_start(&file, start)?;
+        bar.set_position(offset);
+    }
+
+    let mut showed_message = false;
+    let mut eat = |state: &mut State, lineno: usize, l: &[u8]| {
+        if state.eat(l).is_err() && !showed_message {
+            bar.set_message(&format!(
+                "{}: unable to parse line {}: {}",
+                style("warning").bold().yellow(),
+                lineno,
+                String::from_utf8_lossy(l),
+            ));
+            showed_message = true;
+        }
+        if state.should_spill() {
+            state
+                .spill()
+                .with_context(|| format!("moving log file {:?} to disk", file))?;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+
+    if seekable && mmap {
+        let f =
+            std::fs::File::open(&file).with_context(|| format!("opening log file {:?}", file))?;
+        // Safety: the map is only ever read, and log files only ever get
+        // appended to (truncating a file while it is mapped would crash us,
+        // which is why --mmap is not the default)
+        let map = unsafe { memmap2::Mmap::map(&f) }
+            .with_context(|| format!("mapping log file {:?}", file))?;
+        let data = map.get(offset as usize..).unwrap_or(&[]);
+        let mut start = 0;
+        for (lineno, end) in memchr::memchr_iter(b'\n', data).enumerate() {
+            eat(state, lineno + 1, &data[start..=end])?;
+            start = end + 1;
+            if lineno % 4096 == 0 {
+                bar.set_position(offset + start as u64);
+            }
+            if state.past_range {
+                return Ok(());
+            }
+            if interrupt::is_interrupted() {
+                state.interrupted = true;
+                return Ok(());
+            }
+        }
+        if start < data.len() {
+            // The last line was not terminated yet
+            let mut l = data[start..].to_vec();
+            l.push(b'\n');
+            eat(state, 0, &l)?;
+        }
+        bar.set_position(offset + data.len() as u64);
+        return Ok(());
+    }
+
+    let f =
+        input::open_at(&file, offset).with_context(|| format!("opening log file {:?}", file))?;
+    let (_, mut f) = input::decompress(bar.wrap_read(f))
+        .with_context(|| format!("detecting the compression of log file {:?}", file))?;
+    let mut lineno = 0;
+    let mut l = Vec::new();
+    loop {
+        // Read the line
+        lineno += 1;
+        l.truncate(0);
+        let read = f
+            .read_until(b'\n', &mut l)
+            .with_context(|| format!("reading line {} of file {:?}", lineno, file))?;
+        if read == 0 || state.past_range {
+            return Ok(());
+        }
+        if interrupt::is_interrupted() {
+            state.interrupted = true;
+            return Ok(());
+        }
+        if !l.ends_with(b"\n") {
+            // The last line was not terminated yet
+            l.push(b'\n');
+        }
+
+        // Parse the line
+        eat(state, lineno, &l)?;
+    }
+}
+
+// Display the flows of all the transactions matching any of the patterns,
+// returning how many there are
+fn display(
+    patterns: &[search::Pattern],
+    logs: &Logs,
+    summarize_above: Option<usize>,
+    cap: Option<usize>,
+) -> anyhow::Result<usize> {
+    // Search for the blocks that are relevant to the patterns
+    let blocks = patterns
         .iter()
-        .flat_map(|(_, s)| {
-            s.message_ids
-                .get(message_id)
-                .into_iter()
-                .flat_map(|ids| {
-                    ids.iter()
-                        .filter_map(|id| s.blocks.get(id).map(|b| (b.id.clone(), b.clone())))
-                })
-                .collect::<Vec<(String, Block)>>()
-                .into_iter()
+        .flat_map(|p| logs.blocks_with(p))
+        .collect::<BTreeSet<&BlockId>>()
+        .into_iter()
+        .filter_map(|id| logs.blocks.get(id))
+        .collect::<Vec<&Block>>();
+
+    // The deliveries to the recipients that are looked for stand out
+    let recipients = patterns
+        .iter()
+        .filter_map(|p| match p.as_key() {
+            Some(Key::Recipient(r)) => Some(r),
+            _ => None,
         })
-        .collect::<HashMap<String, Block>>();
+        .collect::<HashSet<&String>>();
+    draw(blocks, &recipients, logs, summarize_above, cap)
+}
+
+// Display the flows of the transactions, oldest first, highlighting the
+// deliveries to the recipients, returning how many transactions there are
+fn draw(
+    mut blocks: Vec<&Block>,
+    recipients: &HashSet<&String>,
+    logs: &Logs,
+    summarize_above: Option<usize>,
+    cap: Option<usize>,
+) -> anyhow::Result<usize> {
+    let is_highlighted = |l: LineRef| {
+        if recipients.is_empty() {
+            return false;
+        }
+        let text = format!("{}\n", logs.line(l).text);
+        match ParsedLine::parse(text.as_bytes()) {
+            ParsedLine::Postfix { details, .. } => details.delivery.is_some_and(|d| {
+                std::iter::once(&d.to)
+                    .chain(d.orig_to.iter())
+                    .any(|to| recipients.contains(&normalize_address(to)))
+            }),
+            _ => false,
+        }
+    };
+    blocks.sort_by_key(|b| b.lines.first().map(|&l| (logs.line(l).time, l)));
 
     if blocks.is_empty() {
-        return Ok(false);
+        return Ok(0);
+    }
+    let found = blocks.len();
+    if let Some(cap) = cap.filter(|&cap| blocks.len() > cap) {
+        eprintln!(
+            "{}: {} mails match, only showing the first {}; the search may be too loose",
+            style("warning").bold().yellow(),
+            blocks.len(),
+            cap,
+        );
+        blocks.truncate(cap);
+    }
+    if let Some(max) = summarize_above.filter(|&max| blocks.len() > max) {
+        eprintln!(
+            "{}: {} mails found, more than {}, only showing one line for each; look one up with --queue-id for the details",
+            style("note").bold().cyan(),
+            blocks.len(),
+            max,
+        );
+        for b in blocks {
+            println!("{}", summarize(b, logs));
+        }
+        return Ok(found);
     }
 
     // Helper functions to toposort the blocks
     //
     // (we return BTreeSet's because it makes sure things are properly
     // sorted and the display is reproducible)
-    let predecessors = |id: &str| {
-        states
-            .iter()
-            .flat_map(move |(_, s)| {
-                s.blocks
-                    .get(id)
-                    .into_iter()
-                    .flat_map(|b| b.previous_ids.iter().cloned())
-            })
-            .collect::<BTreeSet<String>>()
+    let predecessors = |id: &BlockId| {
+        logs.blocks
+            .get(id)
+            .map(|b| b.previous_ids.clone())
+            .unwrap_or_default()
     };
-    let successors = |id: &str| {
-        states
-            .iter()
-            .flat_map(move |(_, s)| {
-                s.blocks
-                    .get(id)
-                    .into_iter()
-                    .flat_map(|b| b.next_ids.iter().cloned())
-            })
-            .collect::<BTreeSet<String>>()
+    let successors = |id: &BlockId| {
+        logs.blocks
+            .get(id)
+            .map(|b| b.next_ids.clone())
+            .unwrap_or_default()
     };
 
     // Finally, display all the things
     let mut displayed = HashSet::new();
-    for (id, _) in blocks.iter() {
-        if displayed.contains(&id as &str) {
+    for id in blocks.iter().map(|b| &b.id) {
+        if displayed.contains(id) {
             // Already displayed this
             continue;
         }
@@ -758,30 +2585,98 @@ fn display(message_id: &str, states: HashMap<PathBuf, State>) -> anyhow::Result<
             2,
             &predecessors,
             &successors,
-            &|id| {
-                for (_, s) in states.iter() {
-                    if let Some(b) = s.blocks.get(id) {
-                        return Some(b.clone());
+            &|id| logs.blocks.get(id).cloned(),
+            &|l| logs.line(l).text.clone(),
+            &is_highlighted,
+            &|file| logs.states[file].file.clone(),
+            &mut |id| displayed.insert(id.clone()),
+        );
+    }
+
+    Ok(found)
+}
+
+// One line telling when a mail was submitted, by which client, from whom and
+// what became of each of its recipients, across all the transactions it went
+// through
+fn summarize(block: &Block, logs: &Logs) -> String {
+    let mut client = None;
+    let mut sasl_username = None;
+    let mut from = None;
+    let mut no_message_id = false;
+    // recipient => its last status
+    let mut statuses: Vec<(String, String)> = Vec::new();
+    let mut todo = vec![block];
+    let mut seen = HashSet::new();
+    while let Some(b) = todo.pop() {
+        if !seen.insert(&b.id) {
+            continue;
+        }
+        for &l in b.lines.iter() {
+            let text = format!("{}\n", logs.line(l).text);
+            if let ParsedLine::Postfix { details, .. } = ParsedLine::parse(text.as_bytes()) {
+                let details = *details;
+                client = client.or(details.client);
+                sasl_username = sasl_username.or(details.sasl_username);
+                from = from.or(details.from);
+                no_message_id |= details.no_message_id;
+                if let Some(d) = details.delivery {
+                    let to = d.orig_to.unwrap_or(d.to);
+                    match statuses.iter_mut().find(|(t, _)| *t == to) {
+                        Some((_, status)) => *status = d.status,
+                        None => statuses.push((to, d.status)),
                     }
                 }
-                None
-            },
-            &|path, line| states[path].lines[line].clone(),
-            &mut |id| displayed.insert(id.to_string()),
-        );
+            }
+        }
+        todo.extend(b.next_ids.iter().filter_map(|id| logs.blocks.get(id)));
+    }
+
+    let time = block
+        .lines
+        .first()
+        .and_then(|&l| logs.line(l).time)
+        .map(|t| t.to_string())
+        .unwrap_or_else(|| String::from("?"));
+    let mut res = format!("{}  {}", time, style(&block.id).bold());
+    if let Some(c) = client {
+        res += &format!("  client={}", c);
+    }
+    if let Some(u) = sasl_username {
+        res += &format!("  sasl_username={}", u);
     }
+    if let Some(f) = from {
+        res += &format!("  from=<{}>", f);
+    }
+    for (to, status) in statuses {
+        res += &format!("  to=<{}> ({})", to, status);
+    }
+    if no_message_id {
+        res += "  (no original Message-ID)";
+    }
+    res
+}
 
-    Ok(true)
+// Whether a log line tells that its mail had no message-id of its own
+fn has_no_message_id(line: &str) -> bool {
+    let text = format!("{}\n", line);
+    match ParsedLine::parse(text.as_bytes()) {
+        ParsedLine::Postfix { details, .. } => details.no_message_id,
+        _ => false,
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn display_recursively(
-    root: String,
+    root: BlockId,
     indent: usize,
-    predecessors: &dyn Fn(&str) -> BTreeSet<String>,
-    successors: &dyn Fn(&str) -> BTreeSet<String>,
-    block: &dyn Fn(&str) -> Option<Block>,
-    line: &dyn Fn(&PathBuf, usize) -> String,
-    visit: &mut dyn FnMut(&str) -> bool, // returns true if it's the first visit
+    predecessors: &dyn Fn(&BlockId) -> BTreeSet<BlockId>,
+    successors: &dyn Fn(&BlockId) -> BTreeSet<BlockId>,
+    block: &dyn Fn(&BlockId) -> Option<Block>,
+    line: &dyn Fn(LineRef) -> String,
+    is_highlighted: &dyn Fn(LineRef) -> bool,
+    file: &dyn Fn(usize) -> PathBuf,
+    visit: &mut dyn FnMut(&BlockId) -> bool, // returns true if it's the first visit
 ) {
     if !visit(&root) {
         // already visited (probably while displaying this graph)
@@ -800,47 +2695,58 @@ fn display_recursively(
         }
     };
 
-    // display the root
-    let lines = b
-        .lines
-        .iter()
-        .map(|&l| line(&b.file, l))
-        .collect::<Vec<String>>();
+    // display the root, telling where the lines come from if the block spans
+    // multiple files
+    let spans_files = b.lines.iter().any(|l| l.file != b.lines[0].file);
+    let mut lines = Vec::with_capacity(b.lines.len());
+    let mut current_file = None;
+    for &l in b.lines.iter() {
+        if spans_files && current_file != Some(l.file) {
+            lines.push((format!("(from {:?})", file(l.file)), false));
+            current_file = Some(l.file);
+        }
+        lines.push((line(l), is_highlighted(l)));
+    }
     let width = lines
         .iter()
-        .map(|l| l.len())
+        .map(|(l, _)| l.len())
         .max()
         .expect("block with no lines");
 
     println!();
-    let bonus_header = {
-        let pred = predecessors(&root);
-        if !pred.is_empty() {
-            format!(", coming from {:?}", pred)
-        } else {
-            String::from("")
-        }
-    };
+    let mut bonus_header = String::new();
+    if b.lines.iter().any(|&l| has_no_message_id(&line(l))) {
+        bonus_header += ", no original Message-ID";
+    }
+    let pred = predecessors(&root);
+    if !pred.is_empty() {
+        bonus_header += &format!(", coming from {}", format_ids(&pred));
+    }
     println!(
         "{n:indent$}┌─{title:─<width$}─┐",
         n = "",
-        title = format!("[ {}{} ]", style(&root).bold(), bonus_header,),
+        title = format!("[ {}{} ]", style(&root).bold(), bonus_header),
         indent = indent,
         width = width,
     );
-    for l in lines {
+    for (l, highlighted) in lines {
+        // pad before styling, so that escape codes do not count in the width
+        let l = format!("{l: <width$}", l = l, width = width);
         println!(
-            "{n:indent$}│ {l: <width$} │",
+            "{n:indent$}│ {l} │",
             n = "",
             indent = indent,
-            l = l,
-            width = width,
+            l = if highlighted {
+                style(l).bold().green()
+            } else {
+                style(l)
+            },
         );
     }
     let bonus_footer = {
         let succ = successors(&root);
         if !succ.is_empty() {
-            format!(", flowing into {:?}", succ)
+            format!(", flowing into {}", format_ids(&succ))
         } else {
             String::from("")
         }
@@ -862,6 +2768,8 @@ fn display_recursively(
             successors,
             block,
             line,
+            is_highlighted,
+            file,
             visit,
         );
     }
@@ -885,3 +2793,227 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    // The postfix id and details of a line
+    fn parse(line: &str) -> (QueueId, Details) {
+        match ParsedLine::parse(format!("{}\n", line).as_bytes()) {
+            ParsedLine::Postfix { id, details } => (id, *details),
+            _ => panic!("{:?} is not about a transaction", line),
+        }
+    }
+
+    #[test]
+    fn parse_host_and_instance() {
+        let (id, details) = parse(
+            "Mar 24 10:00:00 mx1 postfix/cleanup[2]: 4F3A2B1C9D: message-id=<abc@example.org>",
+        );
+        assert_eq!(id.host, "mx1");
+        assert_eq!(id.instance, "postfix");
+        assert_eq!(id.id, "4F3A2B1C9D");
+        assert_eq!(details.message_id.as_deref(), Some("<abc@example.org>"));
+
+        let (id, _) = parse(
+            "2026-03-24T10:00:00.123456+01:00 relay.example.org postfix-out/qmgr[3]: 5B6C7D8E9F: removed",
+        );
+        assert_eq!(id.host, "relay.example.org");
+        assert_eq!(id.instance, "postfix-out");
+        assert_eq!(id.id, "5B6C7D8E9F");
+    }
+
+    #[test]
+    fn parse_relayed_delivery() {
+        let (id, details) = parse(
+            "Mar 24 10:00:01 mx1 postfix/smtp[4]: 4F3A2B1C9D: to=<Bob@Example.org>, \
+             relay=relay.example.org[10.0.0.2]:25, delay=1, delays=0/0/0/1, dsn=2.0.0, \
+             status=sent (250 2.0.0 Ok: queued as 5B6C7D8E9F)",
+        );
+        assert_eq!(id.host, "mx1");
+        assert_eq!(details.next_id.as_deref(), Some("5B6C7D8E9F"));
+        let delivery = details.delivery.as_ref().expect("a delivery");
+        assert_eq!(delivery.status, "sent");
+        assert_eq!(relay_host(&delivery.relay), Some("relay.example.org"));
+        assert!(details
+            .keys()
+            .contains(&Key::Recipient(String::from("bob@example.org"))));
+    }
+
+    #[test]
+    fn parse_other_lines() {
+        let parse = |line: &str| ParsedLine::parse(format!("{}\n", line).as_bytes());
+        assert!(
+            parse("Mar 24 10:00:00 mx1 postfix/smtpd[1]: connect from a[192.0.2.1]")
+                == ParsedLine::Useless
+        );
+        assert!(
+            parse("Mar 24 10:00:00 mx1 clamsmtp[1]: 4F3A2B1C9D: whatever") == ParsedLine::Useless
+        );
+        assert!(
+            parse("Mar 24 10:00:00 mx1 dovecot[1]: imap(bob): Logged out") == ParsedLine::Unknown
+        );
+    }
+
+    // The state of a log file holding these lines
+    fn state(index: usize, lines: &[&str]) -> State {
+        let mut state = State::new(index, PathBuf::from(format!("mail.log.{}", index)));
+        for line in lines {
+            let _ = state.eat(format!("{}\n", line).as_bytes());
+        }
+        state
+    }
+
+    fn merge(states: Vec<State>) -> Logs {
+        Logs::merge(states, &indicatif::ProgressBar::hidden())
+    }
+
+    // The ids of the transactions, as displayed, in order
+    fn block_ids(logs: &Logs) -> Vec<String> {
+        let mut ids = logs.blocks.keys().collect::<Vec<_>>();
+        ids.sort();
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn merge_across_rotated_files() {
+        let logs = merge(vec![
+            state(
+                0,
+                &[
+                    "2026-03-01T23:59:58+01:00 mx postfix/cleanup[2]: 4F3A2B1C9D: message-id=<a@example.org>",
+                    "2026-03-01T23:59:59+01:00 mx postfix/qmgr[3]: 4F3A2B1C9D: from=<a@example.org>, size=1, nrcpt=1 (queue active)",
+                ],
+            ),
+            state(
+                1,
+                &[
+                    "2026-03-02T00:00:01+01:00 mx postfix/local[4]: 4F3A2B1C9D: to=<b@example.org>, relay=local, delay=3, delays=0/0/0/3, dsn=2.0.0, status=sent (delivered to mailbox)",
+                    "2026-03-02T00:00:01+01:00 mx postfix/qmgr[3]: 4F3A2B1C9D: removed",
+                ],
+            ),
+        ]);
+        assert_eq!(block_ids(&logs), vec!["mx/4F3A2B1C9D@2026-03-01T23:59:58"]);
+        let block = logs.blocks.values().next().unwrap();
+        let files = block.lines.iter().map(|l| l.file).collect::<Vec<_>>();
+        assert_eq!(files, vec![0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn split_reused_queue_ids() {
+        let logs = merge(vec![state(
+            0,
+            &[
+                // removed, then reused the same day
+                "2026-03-01T08:00:00+01:00 mx postfix/qmgr[3]: 4F3A2B1C9D: from=<a@example.org>, size=1, nrcpt=1 (queue active)",
+                "2026-03-01T08:00:01+01:00 mx postfix/qmgr[3]: 4F3A2B1C9D: removed",
+                "2026-03-01T14:00:00+01:00 mx postfix/qmgr[3]: 4F3A2B1C9D: from=<b@example.org>, size=1, nrcpt=1 (queue active)",
+                // not removed, but not seen for more than a day
+                "2026-03-03T14:00:00+01:00 mx postfix/qmgr[3]: 4F3A2B1C9D: from=<c@example.org>, size=1, nrcpt=1 (queue active)",
+            ],
+        )]);
+        assert_eq!(
+            block_ids(&logs),
+            vec![
+                "mx/4F3A2B1C9D@2026-03-01T08:00:00",
+                "mx/4F3A2B1C9D@2026-03-01T14:00:00",
+                "mx/4F3A2B1C9D@2026-03-03T14:00:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_links_to_the_closest_incarnation() {
+        let relayed = |time: &str| {
+            format!(
+                "2026-03-01T{}+01:00 mx postfix/smtp[5]: 4F3A2B1C9D: to=<b@example.net>, \
+                 relay=relay.example.org[10.0.0.2]:25, delay=1, delays=0/0/0/1, dsn=2.0.0, \
+                 status=sent (250 2.0.0 Ok: queued as 5B6C7D8E9F)",
+                time
+            )
+        };
+        let (near, far) = (relayed("08:00:00"), relayed("12:00:00"));
+        let logs = merge(vec![
+            state(0, &[near.as_str(), far.as_str()]),
+            state(
+                1,
+                &[
+                    "2026-03-01T08:00:01+01:00 relay postfix/qmgr[3]: 5B6C7D8E9F: from=<a@example.org>, size=1, nrcpt=1 (queue active)",
+                    "2026-03-01T08:00:02+01:00 relay postfix/qmgr[3]: 5B6C7D8E9F: removed",
+                ],
+            ),
+        ]);
+        let mx = logs
+            .blocks
+            .values()
+            .find(|b| b.id.queue_id.host == "mx")
+            .unwrap();
+        let next = mx
+            .next_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        // the relay logged nothing about the second mail, which must not be
+        // mistaken for the first one
+        assert_eq!(
+            next,
+            vec![
+                "relay/5B6C7D8E9F@2026-03-01T08:00:01",
+                "relay.example.org/5B6C7D8E9F",
+            ]
+        );
+    }
+
+    #[test]
+    fn drop_lines_copied_to_several_files() {
+        let lines = [
+            "2026-03-01T08:00:00+01:00 mx postfix/qmgr[3]: 4F3A2B1C9D: from=<a@example.org>, size=1, nrcpt=1 (queue active)",
+            "2026-03-01T08:00:01+01:00 mx postfix/qmgr[3]: 4F3A2B1C9D: removed",
+        ];
+        let logs = merge(vec![state(0, &lines), state(1, &lines[1..])]);
+        assert_eq!(block_ids(&logs), vec!["mx/4F3A2B1C9D@2026-03-01T08:00:00"]);
+        let block = logs.blocks.values().next().unwrap();
+        let files = block.lines.iter().map(|l| l.file).collect::<Vec<_>>();
+        assert_eq!(files, vec![0, 0]);
+        assert_eq!(logs.duplicates, vec![0, 1]);
+    }
+
+    #[test]
+    fn same_host() {
+        assert!(is_same_host("mx1", "mx1"));
+        assert!(is_same_host("mx1", "MX1.example.org"));
+        assert!(is_same_host("mx1.example.org", "mx1"));
+        assert!(is_same_host("mx1.example.org", "mx1.Example.org"));
+        assert!(!is_same_host("mx1.example.org", "mx1.example.net"));
+        assert!(!is_same_host("mx1.example.org", "mx2.example.org"));
+        assert!(!is_same_host("mx1", "mx2.example.org"));
+        assert!(!is_same_host("mx", "mx1"));
+    }
+
+    #[test]
+    fn distance() {
+        let at = |h| {
+            NaiveDate::from_ymd_opt(2026, 3, 1)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+        };
+        let max = TimeDelta::hours(2);
+        let within = |t| distance_within(at(t), at(10), at(12), max);
+        assert_eq!(within(11), Some(TimeDelta::zero()));
+        assert_eq!(within(9), Some(TimeDelta::hours(1)));
+        assert_eq!(within(14), Some(TimeDelta::hours(2)));
+        assert_eq!(within(15), None);
+        assert_eq!(within(7), None);
+        assert_eq!(
+            distance_within(None, at(10), at(12), max),
+            Some(TimeDelta::zero())
+        );
+        assert_eq!(
+            distance_within(at(20), None, None, max),
+            Some(TimeDelta::zero())
+        );
+    }
+}
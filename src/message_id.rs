@@ -0,0 +1,138 @@
+// Decode the %XX escapes of `text`, if it has any and they all are valid
+fn url_decode(text: &str) -> Option<String> {
+    if !text.contains('%') {
+        return None;
+    }
+    let bytes = text.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            res.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let hex = bytes.get(i + 1..i + 3)?;
+        if !hex.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        res.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+        i += 3;
+    }
+    String::from_utf8(res).ok()
+}
+
+// `text` without `prefix`, whatever its case
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    if head.eq_ignore_ascii_case(prefix) {
+        Some(&text[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+// Clean up a message-id the way it may have been pasted, eg. along with its
+// header name, as a `mid:` URI or URL-encoded from a webmail, returning it
+// without angle brackets along with what was done to it
+pub fn normalize(arg: &str) -> (String, Vec<&'static str>) {
+    clean(arg, true)
+}
+
+// The same, but leaving the %XX alone, in case they are part of the
+// message-id as it was typed
+pub fn normalize_undecoded(arg: &str) -> String {
+    clean(arg, false).0
+}
+
+fn clean(arg: &str, decode: bool) -> (String, Vec<&'static str>) {
+    let mut steps = Vec::new();
+    let mut id = arg.trim();
+    if id.len() != arg.len() {
+        steps.push("whitespace trimmed");
+    }
+    if let Some(rest) = strip_prefix_ignore_case(id, "message-id:") {
+        id = rest.trim_start();
+        steps.push("header name stripped");
+    }
+    // RFC 2392, where the message-id may be followed by a content-id
+    if let Some(rest) = strip_prefix_ignore_case(id, "mid:") {
+        id = rest.split('/').next().unwrap_or_default();
+        steps.push("mid: URI unwrapped");
+    }
+    let mut id = id.to_string();
+    if let Some(decoded) = url_decode(&id).filter(|_| decode) {
+        id = decoded;
+        steps.push("URL-decoded");
+    }
+    if let Some(bare) = id.strip_prefix('<').and_then(|m| m.strip_suffix('>')) {
+        id = bare.trim().to_string();
+        steps.push("angle brackets stripped");
+    }
+    (id, steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn as_is() {
+        assert_eq!(
+            normalize("abc@example.org"),
+            (String::from("abc@example.org"), vec![])
+        );
+    }
+
+    #[test]
+    fn brackets_and_whitespace() {
+        assert_eq!(
+            normalize(" <abc@example.org>\n"),
+            (
+                String::from("abc@example.org"),
+                vec!["whitespace trimmed", "angle brackets stripped"]
+            )
+        );
+        assert_eq!(normalize("< abc@example.org >").0, "abc@example.org");
+    }
+
+    #[test]
+    fn header_name() {
+        assert_eq!(
+            normalize("message-ID: <abc@example.org>"),
+            (
+                String::from("abc@example.org"),
+                vec!["header name stripped", "angle brackets stripped"]
+            )
+        );
+    }
+
+    #[test]
+    fn mid_uri() {
+        assert_eq!(
+            normalize("MID:abc%40example.org/part1@example.org"),
+            (
+                String::from("abc@example.org"),
+                vec!["mid: URI unwrapped", "URL-decoded"]
+            )
+        );
+    }
+
+    #[test]
+    fn url_encoded() {
+        assert_eq!(normalize("%3Cabc%40example.org%3E").0, "abc@example.org");
+        // escapes that are not valid are left alone
+        assert_eq!(normalize("100%@example.org").0, "100%@example.org");
+        assert_eq!(normalize("abc%4@example.org").0, "abc%4@example.org");
+        assert_eq!(normalize("abc%ZZ@example.org").0, "abc%ZZ@example.org");
+        assert_eq!(normalize("abc%FF@example.org").0, "abc%FF@example.org");
+    }
+
+    #[test]
+    fn undecoded() {
+        assert_eq!(
+            normalize_undecoded("Message-ID: <ab%41cd@example.org>"),
+            "ab%41cd@example.org"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::OnceLock;
+
+use aho_corasick::{AhoCorasick, Anchored, Input, StartKind};
+
+use crate::{NO_ID_PREFIXES, NO_ID_WARNING_PREFIXES};
+
+// The automaton matching the beginnings of the postfix messages that are
+// about no transaction
+fn automaton() -> &'static AhoCorasick {
+    static AUTOMATON: OnceLock<AhoCorasick> = OnceLock::new();
+    AUTOMATON.get_or_init(|| {
+        let patterns = NO_ID_PREFIXES.iter().map(|p| p.to_string()).chain(
+            NO_ID_WARNING_PREFIXES
+                .iter()
+                .map(|p| format!("warning: {}", p)),
+        );
+        AhoCorasick::builder()
+            .start_kind(StartKind::Anchored)
+            .build(patterns)
+            .expect("building the prefilter automaton")
+    })
+}
+
+// The postfix message of a log line, ie. what comes after the syslog
+// timestamp, hostname and `postfix/program[pid]: `
+fn postfix_message(line: &[u8]) -> Option<&[u8]> {
+    let date_len = match line.iter().position(|&c| c == b' ') {
+        Some(i) if i > 0 && line[..i].iter().all(|c| b"0123456789-T:.+Z".contains(c)) => i + 1,
+        _ => "Jan 10 00:00:00 ".len(),
+    };
+    let rest = line.get(date_len..)?;
+    let host_len = rest.iter().position(|&c| c == b' ')?;
+    let rest = &rest[host_len + 1..];
+    if !rest.starts_with(b"postfix") {
+        return None;
+    }
+    let program_len = rest.iter().position(|&c| c == b' ')?;
+    Some(&rest[program_len + 1..])
+}
+
+// Whether the message starts like a postfix id followed by `: `, as the
+// parser takes them, so that `warning: ` or `NOQUEUE: ` are not mistaken for one
+fn starts_with_queue_id(message: &[u8]) -> bool {
+    let len = message
+        .iter()
+        .position(|c| !b"0123456789ABCDEF".contains(c))
+        .unwrap_or(message.len());
+    len > 0 && message[len..].starts_with(b": ")
+}
+
+// Whether a log line is for sure useless, which can be told much faster than
+// by running the whole parser on it
+//
+// This recognizes the postfix lines about no transaction, that make up most
+// of a busy log. Lines that may be about a transaction are left to the parser.
+pub fn is_useless(line: &[u8]) -> bool {
+    let message = match postfix_message(line) {
+        Some(m) => m,
+        None => return false,
+    };
+    if starts_with_queue_id(message) {
+        return false;
+    }
+    automaton()
+        .find(Input::new(message).anchored(Anchored::Yes))
+        .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParsedLine;
+
+    // A log line of `program` with `message`, as each kind of timestamp and
+    // postfix instance writes them
+    fn lines(program: &str, message: &str) -> Vec<String> {
+        let mut res = Vec::new();
+        for date in [
+            "Mar 24 10:00:00",
+            "Mar  4 10:00:00",
+            "2026-03-24T10:00:00.123456+01:00",
+        ] {
+            for instance in ["postfix", "postfix-out"] {
+                res.push(format!(
+                    "{} mx1 {}/{}[1234]: {}\n",
+                    date, instance, program, message
+                ));
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn agrees_with_the_parser_on_useless_lines() {
+        let messages = NO_ID_PREFIXES
+            .iter()
+            .map(|p| format!("{}mx2.example.org[192.0.2.1]", p))
+            .chain(
+                NO_ID_WARNING_PREFIXES
+                    .iter()
+                    .map(|p| format!("warning: {}mx2.example.org[192.0.2.1]", p)),
+            );
+        for message in messages {
+            for line in lines("smtpd", &message) {
+                assert!(is_useless(line.as_bytes()), "{:?} is useless", line);
+                assert!(
+                    ParsedLine::parse_all(line.as_bytes()) == ParsedLine::Useless,
+                    "the parser agrees that {:?} is useless",
+                    line
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_transactions_to_the_parser() {
+        let messages = [
+            "4F3A2B1C9D: client=mx2.example.org[192.0.2.1]",
+            "4F3A2B1C9D: message-id=<connect from @example.org>",
+            "warning: 4F3A2B1C9D: queue file size limit exceeded",
+            "fatal: lock file bounce 4F3A2B1C9D: Resource temporarily unavailable",
+            "warning: hostname mx2.example.org does not resolve to address 192.0.2.1",
+            "warning: connect from nowhere",
+            "Connect from mx2.example.org[192.0.2.1]",
+        ];
+        for message in messages {
+            for line in lines("smtpd", message) {
+                assert!(
+                    !is_useless(line.as_bytes()),
+                    "{:?} is left to the parser",
+                    line
+                );
+            }
+        }
+        // lines that are not postfix ones
+        assert!(!is_useless(
+            b"Mar 24 10:00:00 mx1 dovecot[1]: connect from mx2.example.org\n"
+        ));
+        assert!(!is_useless(b"connect from mx2.example.org\n"));
+        assert!(!is_useless(b"\n"));
+    }
+
+    #[test]
+    fn queue_ids() {
+        assert!(starts_with_queue_id(b"4F3A2B1C9D: removed"));
+        assert!(!starts_with_queue_id(b"4f3a2b1c9d: removed"));
+        assert!(!starts_with_queue_id(b"NOQUEUE: reject"));
+        assert!(!starts_with_queue_id(b"warning: 4F3A2B1C9D: x"));
+        assert!(!starts_with_queue_id(b": removed"));
+        assert!(!starts_with_queue_id(b"4F3A2B1C9D"));
+    }
+}
@@ -0,0 +1,565 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Context};
+use chrono::NaiveDateTime;
+
+use crate::{normalize_address, range, search, Block, Key, Logs, ParsedLine};
+
+// A message-id without the brackets it may be logged with
+fn bare(message_id: &str) -> &str {
+    message_id
+        .strip_prefix('<')
+        .and_then(|m| m.strip_suffix('>'))
+        .unwrap_or(message_id)
+}
+
+// a condition on a message-id, address or host name
+#[derive(Clone, Debug)]
+pub enum Text {
+    Exact(String),
+    Domain(String), // addresses in this domain or its subdomains
+    Glob(glob::Pattern),
+}
+
+impl Text {
+    // Parse a value, that is a glob if it has wildcards, or else a domain if
+    // `domains` are allowed and it has no `@`
+    fn parse(value: &str, domains: bool) -> anyhow::Result<Text> {
+        Ok(if value.contains(|c| "*?[".contains(c)) {
+            Text::Glob(
+                glob::Pattern::new(value).with_context(|| format!("parsing glob ‘{}’", value))?,
+            )
+        } else if domains && !value.trim_start_matches('@').contains('@') {
+            Text::Domain(value.trim_start_matches('@').to_string())
+        } else {
+            Text::Exact(value.to_string())
+        })
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Text::Exact(v) => v == text,
+            Text::Domain(d) => text.rsplit_once('@').is_some_and(|(_, domain)| {
+                domain == d
+                    || domain
+                        .strip_suffix(d.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            }),
+            Text::Glob(g) => g.matches(text),
+        }
+    }
+}
+
+// what can be asked about a mail
+#[derive(Clone, Debug)]
+pub enum Predicate {
+    MessageId(Text),
+    From(Text),
+    To(Text), // be it the final or original recipient
+    Status(String),
+    Relay(Text),
+    Client(search::Pattern),
+    SaslUser(String),
+    QueueId(String),
+    After(NaiveDateTime),
+    Before(NaiveDateTime),
+}
+
+impl Predicate {
+    // Parse a `name:value` predicate
+    fn parse(word: &str) -> anyhow::Result<Predicate> {
+        let (name, value) = match word.split_once(':') {
+            Some((name, value)) if !value.is_empty() => (name, value),
+            _ => bail!(
+                "expected a predicate like from:alice@example.org, found ‘{}’",
+                word
+            ),
+        };
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "message-id" | "mid" => Predicate::MessageId(Text::parse(bare(value), false)?),
+            "from" => Predicate::From(Text::parse(&normalize_address(value), true)?),
+            "to" => Predicate::To(Text::parse(&normalize_address(value), true)?),
+            "status" => Predicate::Status(value.to_ascii_lowercase()),
+            "relay" => Predicate::Relay(Text::parse(&value.to_ascii_lowercase(), false)?),
+            "client" => Predicate::Client(search::Pattern::client(value)?),
+            "sasl-user" | "sasl" => Predicate::SaslUser(value.to_string()),
+            "queue-id" | "id" => Predicate::QueueId(value.to_string()),
+            "after" | "since" => Predicate::After(range::parse_time(value)?),
+            "before" | "until" => Predicate::Before(range::parse_time(value)?),
+            _ => bail!(
+                "unknown predicate ‘{}’, expected one of message-id, from, to, status, relay, client, sasl-user, queue-id, after or before",
+                name
+            ),
+        })
+    }
+
+    fn holds(&self, facts: &Facts) -> bool {
+        let any_key = |f: &dyn Fn(&Key) -> bool| facts.keys.iter().any(f);
+        match self {
+            Predicate::MessageId(t) => {
+                any_key(&|k| matches!(k, Key::MessageId(m) if t.matches(bare(m))))
+            }
+            Predicate::From(t) => any_key(&|k| matches!(k, Key::Sender(s) if t.matches(s))),
+            Predicate::To(t) => any_key(&|k| matches!(k, Key::Recipient(r) if t.matches(r))),
+            Predicate::Status(s) => facts.statuses.contains(s),
+            Predicate::Relay(t) => facts.relays.iter().any(|r| t.matches(r)),
+            Predicate::Client(p) => any_key(&|k| p.matches(k)),
+            Predicate::SaslUser(u) => any_key(&|k| matches!(k, Key::SaslUser(s) if s == u)),
+            Predicate::QueueId(q) => facts.queue_ids.contains(q),
+            Predicate::After(t) => facts.first.is_some_and(|first| first >= *t),
+            Predicate::Before(t) => facts.first.is_some_and(|first| first < *t),
+        }
+    }
+}
+
+// a combination of predicates
+#[derive(Clone, Debug)]
+pub enum Query {
+    Predicate(Predicate),
+    Not(Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+}
+
+// Split a query into parentheses and words, the latter possibly holding
+// double-quoted parts
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut res = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                res.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                res.push(Token::Close);
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    if c != '"' {
+                        word.push(c);
+                        continue;
+                    }
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(c) => word.push(c),
+                            None => bail!("unterminated quote in ‘{}’", input),
+                        }
+                    }
+                }
+                res.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(res)
+}
+
+// a recursive-descent parser over the tokens of a query, where NOT binds
+// tighter than AND, which binds tighter than OR, and predicates next to each
+// other are ANDed
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    // Consume the next token if it is `keyword`
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> anyhow::Result<Query> {
+        let mut res = self.and()?;
+        while self.keyword("OR") {
+            res = Query::Or(Box::new(res), Box::new(self.and()?));
+        }
+        Ok(res)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Query> {
+        let mut res = self.not()?;
+        loop {
+            let next_is_term = match self.peek() {
+                Some(Token::Open) => true,
+                Some(Token::Word(w)) => !w.eq_ignore_ascii_case("OR"),
+                _ => false,
+            };
+            if !next_is_term {
+                return Ok(res);
+            }
+            self.keyword("AND");
+            res = Query::And(Box::new(res), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> anyhow::Result<Query> {
+        if self.keyword("NOT") {
+            return Ok(Query::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> anyhow::Result<Query> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Open) => {
+                let res = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    bail!("missing closing parenthesis");
+                }
+                self.pos += 1;
+                Ok(res)
+            }
+            Some(Token::Word(w)) => Ok(Query::Predicate(Predicate::parse(&w)?)),
+            Some(Token::Close) => bail!("unexpected closing parenthesis"),
+            None => bail!("unexpected end of query"),
+        }
+    }
+}
+
+impl Query {
+    // Parse a query like `from:*@example.org (to:gmail.com OR NOT status:sent)`
+    pub fn parse(input: &str) -> anyhow::Result<Query> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+        };
+        let res = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            bail!("unexpected closing parenthesis in query ‘{}’", input);
+        }
+        Ok(res)
+    }
+
+    // The mails from `from` to `to`, that first showed up between `since` and
+    // `until`
+    pub fn between(
+        from: &str,
+        to: &str,
+        since: Option<NaiveDateTime>,
+        until: Option<NaiveDateTime>,
+    ) -> Query {
+        let mut res = Query::And(
+            Box::new(Query::Predicate(Predicate::From(Text::Exact(
+                normalize_address(from),
+            )))),
+            Box::new(Query::Predicate(Predicate::To(Text::Exact(
+                normalize_address(to),
+            )))),
+        );
+        for p in since
+            .map(Predicate::After)
+            .into_iter()
+            .chain(until.map(Predicate::Before))
+        {
+            res = Query::And(Box::new(res), Box::new(Query::Predicate(p)));
+        }
+        res
+    }
+
+    // The predicates one of which must hold for some transaction of a mail
+    // for the query to hold for it, if there are such predicates: a query
+    // like `NOT status:sent` may hold for mails that match none of them
+    pub fn anchors(&self) -> Option<Vec<&Predicate>> {
+        match self {
+            // the time a mail first showed up depends on all its transactions
+            Query::Predicate(Predicate::After(_) | Predicate::Before(_)) => None,
+            Query::Predicate(p) => Some(vec![p]),
+            Query::Not(_) => None,
+            Query::And(a, b) => a.anchors().or_else(|| b.anchors()),
+            Query::Or(a, b) => {
+                let mut res = a.anchors()?;
+                res.extend(b.anchors()?);
+                Some(res)
+            }
+        }
+    }
+
+    fn holds(&self, facts: &Facts) -> bool {
+        match self {
+            Query::Predicate(p) => p.holds(facts),
+            Query::Not(q) => !q.holds(facts),
+            Query::And(a, b) => a.holds(facts) && b.holds(facts),
+            Query::Or(a, b) => a.holds(facts) || b.holds(facts),
+        }
+    }
+}
+
+// what is known about a mail, across all the transactions it went through
+#[derive(Default)]
+struct Facts {
+    keys: HashSet<Key>,
+    queue_ids: HashSet<String>,
+    statuses: HashSet<String>,
+    relays: HashSet<String>, // lowercased host names
+    first: Option<NaiveDateTime>,
+}
+
+impl Facts {
+    fn add(&mut self, block: &Block, logs: &Logs) {
+        self.queue_ids.insert(block.id.queue_id.id.clone());
+        for &l in block.lines.iter() {
+            let line = logs.line(l);
+            if let Some(t) = line.time {
+                self.first = Some(self.first.map_or(t, |first| first.min(t)));
+            }
+            self.add_line(format!("{}\n", line.text).as_bytes());
+        }
+    }
+
+    // Add what a line, with its trailing \n, tells apart from its time
+    fn add_line(&mut self, line: &[u8]) {
+        if let ParsedLine::Postfix { id, details } = ParsedLine::parse(line) {
+            self.queue_ids.insert(id.id);
+            self.keys.extend(details.keys());
+            if let Some(d) = details.delivery {
+                let relay = d.relay.split('[').next().unwrap_or_default();
+                self.relays.insert(relay.to_ascii_lowercase());
+                self.statuses.insert(d.status);
+            }
+        }
+    }
+}
+
+// Whether any of the predicates holds for a transaction with these lines,
+// each with its trailing \n
+pub fn any_holds<'a>(predicates: &[&Predicate], lines: impl Iterator<Item = &'a [u8]>) -> bool {
+    let mut facts = Facts::default();
+    for line in lines {
+        facts.add_line(line);
+    }
+    predicates.iter().any(|p| p.holds(&facts))
+}
+
+// The first transaction of each mail the query holds for, oldest first, a
+// mail being all the transactions that are linked together
+pub fn matching<'a>(query: &Query, logs: &'a Logs) -> Vec<&'a Block> {
+    let mut blocks = logs.blocks.values().collect::<Vec<&Block>>();
+    blocks.sort_by_key(|b| b.lines.first().map(|&l| (logs.line(l).time, l)));
+    let mut seen = HashSet::new();
+    let mut res = Vec::new();
+    for b in blocks {
+        if seen.contains(&b.id) {
+            continue;
+        }
+        let mut facts = Facts::default();
+        let mut todo = vec![&b.id];
+        while let Some(id) = todo.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+            if let Some(block) = logs.blocks.get(id) {
+                facts.add(block, logs);
+                todo.extend(block.previous_ids.iter());
+                todo.extend(block.next_ids.iter());
+            }
+        }
+        if query.holds(&facts) {
+            res.push(b);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The structure of a query over status: predicates, with every
+    // combination in parentheses
+    fn shape(query: &Query) -> String {
+        match query {
+            Query::Predicate(Predicate::Status(s)) => s.clone(),
+            Query::Predicate(p) => format!("{:?}", p),
+            Query::Not(q) => format!("NOT {}", shape(q)),
+            Query::And(a, b) => format!("({} AND {})", shape(a), shape(b)),
+            Query::Or(a, b) => format!("({} OR {})", shape(a), shape(b)),
+        }
+    }
+
+    fn parse(query: &str) -> String {
+        shape(&Query::parse(query).unwrap())
+    }
+
+    #[test]
+    fn tokenize_words_and_parentheses() {
+        let word = |w: &str| Token::Word(w.to_string());
+        assert_eq!(
+            tokenize("(from:a@b.c OR\tto:d)x").unwrap(),
+            vec![
+                Token::Open,
+                word("from:a@b.c"),
+                word("OR"),
+                word("to:d"),
+                Token::Close,
+                word("x"),
+            ]
+        );
+        assert_eq!(tokenize("  ").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn tokenize_quotes() {
+        assert_eq!(
+            tokenize(r#"before:"2026-10-01 12:00" ("a b")"#).unwrap(),
+            vec![
+                Token::Word(String::from("before:2026-10-01 12:00")),
+                Token::Open,
+                Token::Word(String::from("a b")),
+                Token::Close,
+            ]
+        );
+        assert!(tokenize(r#"before:"2026-10-01 12:00"#).is_err());
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            parse("status:a OR status:b AND status:c"),
+            "(a OR (b AND c))"
+        );
+        assert_eq!(
+            parse("status:a AND status:b OR status:c"),
+            "((a AND b) OR c)"
+        );
+        assert_eq!(parse("NOT status:a AND status:b"), "(NOT a AND b)");
+        assert_eq!(parse("NOT (status:a OR status:b)"), "NOT (a OR b)");
+        assert_eq!(parse("not status:a or status:b"), "(NOT a OR b)");
+    }
+
+    #[test]
+    fn implicit_and() {
+        assert_eq!(parse("status:a status:b OR status:c"), "((a AND b) OR c)");
+        assert_eq!(
+            parse("status:a (status:b OR status:c) NOT status:d"),
+            "((a AND (b OR c)) AND NOT d)"
+        );
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(parse("((status:a))"), "a");
+        assert!(Query::parse("status:a)").is_err());
+        assert!(Query::parse("(status:a OR status:b))").is_err());
+        assert!(Query::parse(")").is_err());
+        assert!(Query::parse("(status:a").is_err());
+        assert!(Query::parse("()").is_err());
+        assert!(Query::parse("").is_err());
+        assert!(Query::parse("status:a OR").is_err());
+    }
+
+    #[test]
+    fn predicates() {
+        assert!(matches!(
+            Query::parse("mid:<abc@example.org>").unwrap(),
+            Query::Predicate(Predicate::MessageId(Text::Exact(m))) if m == "abc@example.org"
+        ));
+        assert!(matches!(
+            Query::parse("FROM:<Alice@Example.org>").unwrap(),
+            Query::Predicate(Predicate::From(Text::Exact(a))) if a == "alice@example.org"
+        ));
+        assert!(Query::parse("from:").is_err());
+        assert!(Query::parse("alice@example.org").is_err());
+        assert!(Query::parse("subject:hello").is_err());
+    }
+
+    #[test]
+    fn anchors() {
+        let anchors = |query: &str| {
+            Query::parse(query)
+                .unwrap()
+                .anchors()
+                .map(|a| a.iter().map(|p| format!("{:?}", p)).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            anchors("status:a status:b"),
+            Some(vec![String::from(r#"Status("a")"#)])
+        );
+        assert_eq!(
+            anchors("NOT status:a status:b"),
+            Some(vec![String::from(r#"Status("b")"#)])
+        );
+        assert_eq!(
+            anchors("status:a OR status:b"),
+            Some(vec![
+                String::from(r#"Status("a")"#),
+                String::from(r#"Status("b")"#)
+            ])
+        );
+        assert_eq!(anchors("status:a OR NOT status:b"), None);
+        assert_eq!(anchors("NOT status:a"), None);
+        assert_eq!(anchors("after:2026-01-01"), None);
+    }
+
+    #[test]
+    fn text_parse() {
+        assert!(
+            matches!(Text::parse("example.org", true).unwrap(), Text::Domain(d) if d == "example.org")
+        );
+        assert!(
+            matches!(Text::parse("@example.org", true).unwrap(), Text::Domain(d) if d == "example.org")
+        );
+        assert!(matches!(
+            Text::parse("example.org", false).unwrap(),
+            Text::Exact(_)
+        ));
+        assert!(matches!(
+            Text::parse("a@example.org", true).unwrap(),
+            Text::Exact(_)
+        ));
+        assert!(matches!(
+            Text::parse("*@example.org", true).unwrap(),
+            Text::Glob(_)
+        ));
+        assert!(Text::parse("[a", true).is_err());
+    }
+
+    #[test]
+    fn domain_matches() {
+        let domain = Text::parse("example.org", true).unwrap();
+        assert!(domain.matches("alice@example.org"));
+        assert!(domain.matches("alice@mx.example.org"));
+        assert!(!domain.matches("alice@badexample.org"));
+        assert!(!domain.matches("alice@example.org.net"));
+        assert!(!domain.matches("example.org"));
+    }
+
+    #[test]
+    fn glob_matches() {
+        let glob = Text::parse("*@example.org", true).unwrap();
+        assert!(glob.matches("alice@example.org"));
+        assert!(!glob.matches("alice@mx.example.org"));
+        let glob = Text::parse("mx?.example.org", false).unwrap();
+        assert!(glob.matches("mx1.example.org"));
+        assert!(!glob.matches("mx10.example.org"));
+    }
+}
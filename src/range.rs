@@ -0,0 +1,265 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+use crate::{discover, input, timestamp};
+
+// Parse a duration like `90s`, `30m`, `2h`, `3d` or `1w`
+pub fn parse_duration(arg: &str) -> anyhow::Result<Duration> {
+    let split = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let (n, unit) = arg.split_at(split);
+    let n: i64 = n
+        .parse()
+        .with_context(|| format!("parsing the number in duration ‘{}’", arg))?;
+    let duration = match unit {
+        "s" => Duration::try_seconds(n),
+        "m" => Duration::try_minutes(n),
+        "h" | "" => Duration::try_hours(n),
+        "d" => Duration::try_days(n),
+        "w" => Duration::try_weeks(n),
+        _ => bail!(
+            "unknown unit ‘{}’ in duration ‘{}’, expected s, m, h, d or w",
+            unit,
+            arg
+        ),
+    };
+    duration.with_context(|| format!("duration ‘{}’ is too long", arg))
+}
+
+// Parse a point in time, either absolute (`2021-03-24`, `2021-03-24 10:00`,
+// `2021-03-24T10:00:00`) or relative to now (`2h` ago, `yesterday`, `today`)
+pub fn parse_time(arg: &str) -> anyhow::Result<NaiveDateTime> {
+    let now = chrono::Local::now().naive_local();
+    let today = now
+        .date()
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time");
+    match arg {
+        "now" => return Ok(now),
+        "today" => return Ok(today),
+        "yesterday" => return Ok(today - Duration::days(1)),
+        _ => (),
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    {
+        if let Ok(t) = NaiveDateTime::parse_from_str(arg, format) {
+            return Ok(t);
+        }
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(arg, "%Y-%m-%d") {
+        return Ok(d.and_hms_opt(0, 0, 0).expect("midnight is a valid time"));
+    }
+    let is_duration = arg.starts_with(|c: char| c.is_ascii_digit())
+        && arg
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .chars()
+            .all(|c| "smhdw".contains(c));
+    match parse_duration(arg) {
+        Ok(ago) => now
+            .checked_sub_signed(ago)
+            .with_context(|| format!("‘{}’ ago is too far back in time", arg)),
+        Err(e) if is_duration => Err(e),
+        Err(_) => bail!(
+            "unable to parse ‘{}’ as a time, expected eg. 2021-03-24, ‘2021-03-24 10:00’, 2h, today or yesterday",
+            arg
+        ),
+    }
+}
+
+// the times between which log lines are worth reading
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Range {
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+}
+
+impl Range {
+    pub fn is_unbounded(&self) -> bool {
+        self.start.is_none() && self.end.is_none()
+    }
+
+    // Whether a line logged at `time` is in range, lines with no known time
+    // always being kept
+    pub fn contains(&self, time: Option<NaiveDateTime>) -> bool {
+        match time {
+            Some(t) => {
+                self.start.map(|s| s <= t).unwrap_or(true)
+                    && self.end.map(|e| t <= e).unwrap_or(true)
+            }
+            None => true,
+        }
+    }
+
+    // Whether the range ends before `time`
+    pub fn ends_before(&self, time: Option<NaiveDateTime>) -> bool {
+        matches!((self.end, time), (Some(end), Some(t)) if end < t)
+    }
+
+    // Whether something that went on from `first` to `last` may be in range
+    pub fn overlaps(&self, first: Option<NaiveDateTime>, last: Option<NaiveDateTime>) -> bool {
+        !matches!((self.start, last), (Some(start), Some(last)) if last < start)
+            && !matches!((self.end, first), (Some(end), Some(first)) if first > end)
+    }
+
+    // Whether a log file may have lines in range
+    //
+    // The file is assumed to start with its first timestamp and end at its
+    // modification time, which also is what the year of syslog timestamps is
+    // guessed from. Anything but a regular file may, as reading its start would
+    // consume it.
+    pub fn may_overlap(&self, file: &Path) -> bool {
+        if !input::is_regular(file) || self.is_unbounded() {
+            return true;
+        }
+        self.overlaps(
+            discover::first_timestamp(file),
+            Some(timestamp::reference(file)),
+        )
+    }
+}
+
+// The timestamp of the first line starting after `offset` that has one, or
+// None if there is none until the end of the file
+fn time_after(
+    f: &mut File,
+    offset: u64,
+    reference: NaiveDateTime,
+) -> std::io::Result<Option<NaiveDateTime>> {
+    f.seek(SeekFrom::Start(offset))?;
+    let mut f = BufReader::new(f);
+    let mut line = Vec::new();
+    if offset > 0 {
+        // Skip the end of the line we landed in
+        f.read_until(b'\n', &mut line)?;
+    }
+    loop {
+        line.clear();
+        if f.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        if let Some(t) = timestamp::parse(&line, reference) {
+            return Ok(Some(t));
+        }
+    }
+}
+
+// Find the offset from which to read an uncompressed log file so as to skip
+// the lines logged before `start`
+//
+// This bisects the file, assuming it is sorted as log files are, up to clock
+// adjustments that may make a few lines around the offset out of order.
+pub fn seek_start(file: &Path, start: NaiveDateTime) -> anyhow::Result<u64> {
+    let mut f = File::open(file).with_context(|| format!("opening log file {:?}", file))?;
+    let reference = timestamp::reference(file);
+    let (mut lo, mut hi) = (
+        0,
+        f.metadata()
+            .with_context(|| format!("retrieving metadata for log file {:?}", file))?
+            .len(),
+    );
+    // Invariant: the first line starting after `lo` is before `start`
+    while lo + 1 < hi {
+        let mid = lo + (hi - lo) / 2;
+        let time = time_after(&mut f, mid, reference)
+            .with_context(|| format!("looking for {} in log file {:?}", start, file))?;
+        match time {
+            Some(t) if t < start => lo = mid,
+            _ => hi = mid,
+        }
+    }
+    if lo == 0 {
+        // The invariant does not hold yet if the first line is not before
+        // `start`, in which case nothing is to be skipped
+        let first = time_after(&mut f, 0, reference)
+            .with_context(|| format!("looking for {} in log file {:?}", start, file))?;
+        if !matches!(first, Some(t) if t < start) {
+            return Ok(0);
+        }
+    }
+    // `lo` ends up just before the last line before `start`, which is the
+    // line `hi` is in: start at the beginning of the line following it
+    f.seek(SeekFrom::Start(lo + 1))
+        .with_context(|| format!("seeking into log file {:?}", file))?;
+    let mut skipped = Vec::new();
+    BufReader::new(&mut f)
+        .read_until(b'\n', &mut skipped)
+        .with_context(|| format!("reading log file {:?}", file))?;
+    Ok(lo + 1 + skipped.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::seconds(90));
+        assert_eq!(parse_duration("2").unwrap(), Duration::hours(2));
+        assert_eq!(parse_duration("1w").unwrap(), Duration::days(7));
+        assert!(parse_duration("2y").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("99999999999999h").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn times() {
+        let at = |h, m| {
+            NaiveDate::from_ymd_opt(2021, 3, 24)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+        };
+        assert_eq!(parse_time("2021-03-24").ok(), at(0, 0));
+        assert_eq!(parse_time("2021-03-24 10:00").ok(), at(10, 0));
+        assert_eq!(parse_time("2021-03-24T10:00:00").ok(), at(10, 0));
+        assert!(parse_time("2021-03-32").is_err());
+        assert!(parse_time("100000000w").is_err());
+        assert!(parse_time("99999999999999h").is_err());
+    }
+
+    #[test]
+    fn seek_past_earlier_lines() {
+        let file = std::env::temp_dir().join(format!("mailparse-seek-{}.log", std::process::id()));
+        // where each line ends
+        let mut ends = Vec::new();
+        let mut text = String::new();
+        for m in 0..60 {
+            text += &format!(
+                "2021-03-24T10:{:02}:00+01:00 mx postfix/qmgr[3]: 4F3A2B1C9D: removed\n",
+                m
+            );
+            ends.push(text.len() as u64);
+            if m % 7 == 0 {
+                // lines without a timestamp are kept with the next line
+                text += "  continued\n";
+            }
+        }
+        std::fs::write(&file, &text).unwrap();
+
+        let at = |h, m| {
+            NaiveDate::from_ymd_opt(2021, 3, 24)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        assert_eq!(seek_start(&file, at(9, 0)).unwrap(), 0);
+        assert_eq!(seek_start(&file, at(10, 0)).unwrap(), 0);
+        assert_eq!(seek_start(&file, at(10, 1)).unwrap(), ends[0]);
+        assert_eq!(seek_start(&file, at(10, 30)).unwrap(), ends[29]);
+        assert_eq!(seek_start(&file, at(10, 59)).unwrap(), ends[58]);
+        assert_eq!(seek_start(&file, at(11, 0)).unwrap(), text.len() as u64);
+
+        std::fs::remove_file(&file).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use std::net::IpAddr;
+
+use anyhow::{bail, Context};
+
+use crate::{summary::Summary, Key};
+
+// The number of bits of an address
+fn bits(addr: &IpAddr) -> u32 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+// a range of IP addresses, like 203.0.113.0/24 or 2001:db8::/32
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl Cidr {
+    // Parse a range, or a single address
+    pub fn parse(arg: &str) -> anyhow::Result<Cidr> {
+        let (addr, prefix) = match arg.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (arg, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .with_context(|| format!("parsing the address in ‘{}’", arg))?;
+        let prefix = match prefix {
+            Some(p) => p
+                .parse()
+                .with_context(|| format!("parsing the prefix length in ‘{}’", arg))?,
+            None => bits(&addr),
+        };
+        if prefix > bits(&addr) {
+            bail!("prefix length {} is too long for ‘{}’", prefix, addr);
+        }
+        Ok(Cidr { addr, prefix })
+    }
+
+    pub fn is_single(&self) -> bool {
+        self.prefix == bits(&self.addr)
+    }
+
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        // The top `prefix` bits of `bits`-bit numbers
+        let top = |n: u128, bits: u32| {
+            if self.prefix == 0 {
+                0
+            } else {
+                n >> (bits - self.prefix)
+            }
+        };
+        match (self.addr, addr) {
+            (IpAddr::V4(a), IpAddr::V4(b)) => {
+                top(u32::from(a) as u128, 32) == top(u32::from(*b) as u128, 32)
+            }
+            (IpAddr::V6(a), IpAddr::V6(b)) => top(u128::from(a), 128) == top(u128::from(*b), 128),
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+// the keys that can be matched as text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    MessageId,
+    Sender,
+    Recipient,
+}
+
+impl Field {
+    // The text of a key of this field, if it is one
+    fn text<'a>(&self, key: &'a Key) -> Option<&'a str> {
+        match (self, key) {
+            // brackets are left out, as message-ids are logged with or without
+            (Field::MessageId, Key::MessageId(m)) => Some(
+                m.strip_prefix('<')
+                    .and_then(|m| m.strip_suffix('>'))
+                    .unwrap_or(m),
+            ),
+            (Field::Sender, Key::Sender(s)) => Some(s),
+            (Field::Recipient, Key::Recipient(r)) => Some(r),
+            _ => None,
+        }
+    }
+
+    // Whether the keys of this field are addresses, that are lowercased
+    fn is_address(&self) -> bool {
+        *self != Field::MessageId
+    }
+}
+
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Field::MessageId => write!(f, "message-id"),
+            Field::Sender => write!(f, "sender"),
+            Field::Recipient => write!(f, "recipient"),
+        }
+    }
+}
+
+// how text is matched, besides exactly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Matching {
+    Substring,
+    Glob,
+    Regex,
+}
+
+// something to match text against
+#[derive(Clone, Debug)]
+pub enum Matcher {
+    Substring(String),
+    Glob(glob::Pattern),
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    // Build a matcher for `field`, ignoring case for addresses
+    pub fn new(matching: Matching, field: Field, arg: &str) -> anyhow::Result<Matcher> {
+        let arg = if field.is_address() {
+            arg.to_lowercase()
+        } else {
+            arg.to_string()
+        };
+        Ok(match matching {
+            Matching::Substring => Matcher::Substring(arg),
+            Matching::Glob => Matcher::Glob(
+                glob::Pattern::new(&arg).with_context(|| format!("parsing glob ‘{}’", arg))?,
+            ),
+            Matching::Regex => Matcher::Regex(
+                regex::RegexBuilder::new(&arg)
+                    .case_insensitive(field.is_address())
+                    .build()
+                    .with_context(|| format!("parsing regex ‘{}’", arg))?,
+            ),
+        })
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Substring(s) => text.contains(s.as_str()),
+            Matcher::Glob(g) => g.matches(text),
+            Matcher::Regex(r) => r.is_match(text),
+        }
+    }
+}
+
+impl std::fmt::Display for Matcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Matcher::Substring(s) => write!(f, "containing ‘{}’", s),
+            Matcher::Glob(g) => write!(f, "matching glob ‘{}’", g),
+            Matcher::Regex(r) => write!(f, "matching regex ‘{}’", r),
+        }
+    }
+}
+
+// what picks transactions by their keys
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Exact(Key),           // this very key, that can be looked up directly
+    ClientAddr(Cidr),     // a client address in this range
+    Text(Field, Matcher), // a message-id or address that matches
+}
+
+impl Pattern {
+    // Parse what --client looks for: an address, a range of addresses or a
+    // host name
+    pub fn client(arg: &str) -> anyhow::Result<Pattern> {
+        let is_address = arg.contains('/') || arg.parse::<IpAddr>().is_ok();
+        if !is_address {
+            return Ok(Pattern::Exact(Key::ClientName(arg.to_lowercase())));
+        }
+        let cidr = Cidr::parse(arg)?;
+        Ok(if cidr.is_single() {
+            Pattern::Exact(Key::ClientAddr(cidr.addr))
+        } else {
+            Pattern::ClientAddr(cidr)
+        })
+    }
+
+    // The key this looks for, if it is only one
+    pub fn as_key(&self) -> Option<&Key> {
+        match self {
+            Pattern::Exact(k) => Some(k),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, key: &Key) -> bool {
+        match (self, key) {
+            (Pattern::Exact(k), _) => k == key,
+            (Pattern::ClientAddr(cidr), Key::ClientAddr(a)) => cidr.contains(a),
+            (Pattern::Text(field, m), _) => field.text(key).is_some_and(|t| m.is_match(t)),
+            _ => false,
+        }
+    }
+
+    // Whether a log file may be about this, according to its summary
+    pub fn may_be_in(&self, summary: &Summary) -> bool {
+        match self {
+            Pattern::Exact(k) => k.may_be_in(summary),
+            _ => true,
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Pattern::Exact(k) => write!(f, "{}", k),
+            Pattern::ClientAddr(cidr) => write!(f, "client in ‘{}’", cidr),
+            Pattern::Text(field, m) => write!(f, "{} {}", field, m),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(a: &str) -> IpAddr {
+        a.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_parse() {
+        assert!(Cidr::parse("203.0.113.7").unwrap().is_single());
+        assert!(Cidr::parse("2001:db8::1").unwrap().is_single());
+        assert!(!Cidr::parse("203.0.113.0/24").unwrap().is_single());
+        assert!(Cidr::parse("203.0.113.0/33").is_err());
+        assert!(Cidr::parse("2001:db8::/129").is_err());
+        assert!(Cidr::parse("203.0.113.0/x").is_err());
+        assert!(Cidr::parse("mx.example.org").is_err());
+    }
+
+    #[test]
+    fn cidr_contains_v4() {
+        let cidr = Cidr::parse("203.0.113.0/24").unwrap();
+        assert!(cidr.contains(&addr("203.0.113.0")));
+        assert!(cidr.contains(&addr("203.0.113.255")));
+        assert!(!cidr.contains(&addr("203.0.114.1")));
+        assert!(!cidr.contains(&addr("::ffff:203.0.113.1")));
+        let single = Cidr::parse("203.0.113.7").unwrap();
+        assert!(single.contains(&addr("203.0.113.7")));
+        assert!(!single.contains(&addr("203.0.113.6")));
+    }
+
+    #[test]
+    fn cidr_contains_v6() {
+        let cidr = Cidr::parse("2001:db8::/32").unwrap();
+        assert!(cidr.contains(&addr("2001:db8:1::1")));
+        assert!(!cidr.contains(&addr("2001:db9::1")));
+        assert!(!cidr.contains(&addr("32.1.13.184")));
+    }
+
+    #[test]
+    fn cidr_contains_everything() {
+        let v4 = Cidr::parse("0.0.0.0/0").unwrap();
+        assert!(v4.contains(&addr("0.0.0.0")));
+        assert!(v4.contains(&addr("255.255.255.255")));
+        assert!(!v4.contains(&addr("::1")));
+        let v6 = Cidr::parse("::/0").unwrap();
+        assert!(v6.contains(&addr("::1")));
+        assert!(v6.contains(&addr("ffff::1")));
+        assert!(!v6.contains(&addr("203.0.113.7")));
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{File, OpenOptions},
+    hash::{Hash, Hasher},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    os::unix::fs::FileExt,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use anyhow::{bail, Context};
+
+// Parse a size like `4096`, `512K`, `300M` or `4G`
+pub fn parse_size(arg: &str) -> anyhow::Result<u64> {
+    let split = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let (n, unit) = arg.split_at(split);
+    let n: u64 = n
+        .parse()
+        .with_context(|| format!("parsing the number in size ‘{}’", arg))?;
+    let multiplier: u64 = match unit {
+        "" | "B" => 1,
+        "K" | "KB" | "KiB" => 1 << 10,
+        "M" | "MB" | "MiB" => 1 << 20,
+        "G" | "GB" | "GiB" => 1 << 30,
+        "T" | "TB" | "TiB" => 1 << 40,
+        _ => bail!(
+            "unknown unit ‘{}’ in size ‘{}’, expected K, M, G or T",
+            unit,
+            arg
+        ),
+    };
+    n.checked_mul(multiplier)
+        .with_context(|| format!("size ‘{}’ is too large", arg))
+}
+
+// how much memory the loaded log lines may take, shared by all the files
+// being loaded
+#[derive(Debug)]
+pub struct Budget {
+    limit: u64,
+    used: AtomicU64,
+}
+
+impl Budget {
+    pub fn new(limit: u64) -> Budget {
+        Budget {
+            limit,
+            used: AtomicU64::new(0),
+        }
+    }
+
+    pub fn take(&self, bytes: usize) {
+        self.used.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn give_back(&self, bytes: usize) {
+        self.used.fetch_sub(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn is_exceeded(&self) -> bool {
+        self.used.load(Ordering::Relaxed) > self.limit
+    }
+}
+
+// The hash of an id, to index the spilled chunks compactly
+pub fn hash<T: Hash + ?Sized>(id: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    id.hash(&mut hasher);
+    hasher.finish()
+}
+
+// a chunk that was moved to disk, with what is needed to tell whether it is
+// related to a message
+#[derive(Debug)]
+pub struct SpilledChunk {
+    offset: u64,
+    len: usize,
+    pub queue_id: u64,   // the hash of its postfix id
+    pub links: Vec<u64>, // the hashes of the postfix ids it links to
+    pub keys: Vec<u64>,  // the hashes of its message-ids, senders, etc.
+}
+
+// Open an anonymous temporary file
+fn temp_file() -> std::io::Result<File> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "mailparse-spill-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    // The file lives on as long as it is open
+    std::fs::remove_file(&path)?;
+    Ok(file)
+}
+
+// reads a file from its start, without moving the cursor it is written at
+struct ReadAt<'a> {
+    file: &'a File,
+    pos: u64,
+}
+
+impl Read for ReadAt<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.file.read_at(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// Read the next chunk of an index, as written by `Spill::write`
+fn read_chunk(reader: &mut impl Read) -> std::io::Result<SpilledChunk> {
+    let offset = read_u64(reader)?;
+    let len = read_u64(reader)? as usize;
+    let queue_id = read_u64(reader)?;
+    let links = read_u64(reader)?;
+    let keys = read_u64(reader)?;
+    Ok(SpilledChunk {
+        offset,
+        len,
+        queue_id,
+        links: (0..links)
+            .map(|_| read_u64(reader))
+            .collect::<Result<_, _>>()?,
+        keys: (0..keys)
+            .map(|_| read_u64(reader))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+// anonymous temporary files holding the lines of the chunks of a log file
+// that did not fit in memory, along with an index of the chunks, so that
+// nothing is kept in memory for each chunk
+#[derive(Debug)]
+pub struct Spill {
+    lines: BufWriter<File>,
+    len: u64,
+    index: BufWriter<File>,
+}
+
+impl Spill {
+    // roughly how much memory a spill takes, whatever the number of chunks
+    pub const FOOTPRINT: usize = 2 * 8192;
+
+    pub fn new() -> std::io::Result<Spill> {
+        Ok(Spill {
+            lines: BufWriter::new(temp_file()?),
+            len: 0,
+            index: BufWriter::new(temp_file()?),
+        })
+    }
+
+    // Append the lines of a chunk
+    pub fn write<'a>(
+        &mut self,
+        lines: impl Iterator<Item = &'a str>,
+        queue_id: u64,
+        links: Vec<u64>,
+        keys: Vec<u64>,
+    ) -> std::io::Result<()> {
+        let offset = self.len;
+        for l in lines {
+            self.lines.write_all(l.as_bytes())?;
+            self.lines.write_all(b"\n")?;
+            self.len += l.len() as u64 + 1;
+        }
+        let header = [
+            offset,
+            self.len - offset,
+            queue_id,
+            links.len() as u64,
+            keys.len() as u64,
+        ];
+        for n in header.iter().chain(links.iter()).chain(keys.iter()) {
+            self.index.write_all(&n.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    // Make everything written so far readable
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.lines.flush()?;
+        self.index.flush()
+    }
+
+    // The chunks written so far, in order, read from the index one at a time
+    pub fn chunks(&self) -> impl Iterator<Item = std::io::Result<SpilledChunk>> + '_ {
+        let mut reader = BufReader::new(ReadAt {
+            file: self.index.get_ref(),
+            pos: 0,
+        });
+        std::iter::from_fn(move || match reader.fill_buf() {
+            Ok([]) => None,
+            Ok(_) => Some(read_chunk(&mut reader)),
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    // The lines of a chunk, each with its trailing \n
+    pub fn read(&self, chunk: &SpilledChunk) -> std::io::Result<Vec<u8>> {
+        let mut buf = vec![0; chunk.len];
+        self.lines.get_ref().read_exact_at(&mut buf, chunk.offset)?;
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("512K").unwrap(), 512 << 10);
+        assert_eq!(parse_size("300MiB").unwrap(), 300 << 20);
+        assert_eq!(parse_size("4G").unwrap(), 4 << 30);
+        assert!(parse_size("4X").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("99999999999T").is_err());
+        assert!(parse_size("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut spill = Spill::new().unwrap();
+        spill
+            .write(["first", "second"].iter().copied(), 1, vec![2, 3], vec![4])
+            .unwrap();
+        spill
+            .write(["third"].iter().copied(), 5, Vec::new(), vec![6, 7])
+            .unwrap();
+        spill.flush().unwrap();
+        let chunks = spill.chunks().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            (chunks[0].queue_id, &chunks[0].links, &chunks[0].keys),
+            (1, &vec![2, 3], &vec![4])
+        );
+        assert_eq!(
+            (chunks[1].queue_id, &chunks[1].links, &chunks[1].keys),
+            (5, &vec![], &vec![6, 7])
+        );
+        assert_eq!(spill.read(&chunks[0]).unwrap(), b"first\nsecond\n");
+        assert_eq!(spill.read(&chunks[1]).unwrap(), b"third\n");
+    }
+}
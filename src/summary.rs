@@ -0,0 +1,318 @@
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{ensure, Context};
+use chrono::{DateTime, NaiveDateTime};
+
+use crate::{input, ParsedLine, State};
+
+// the first bytes of summary files, to be bumped whenever their format changes
+const MAGIC: &[u8] = b"mailparse-summary 1\n";
+
+// the rate of false positives the bloom filters are sized for
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+// what a log file looked like when it was summarized, to notice it changed since
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stamp {
+    size: u64,
+    mtime: u64, // in nanoseconds since the epoch
+}
+
+impl Stamp {
+    pub fn of(file: &Path) -> std::io::Result<Stamp> {
+        let meta = std::fs::metadata(file)?;
+        let mtime = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok(Stamp {
+            size: meta.len(),
+            mtime,
+        })
+    }
+}
+
+// FNV-1a, which unlike the standard library hashers is stable across builds
+fn fnv1a(key: &[u8], offset: u64) -> u64 {
+    key.iter().fold(offset, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+// a set of strings, that may wrongly claim to contain a string it does not
+// but never the other way around
+struct Bloom {
+    hashes: u32,
+    bits: Vec<u64>,
+}
+
+impl Bloom {
+    fn new(keys: usize) -> Bloom {
+        let keys = keys.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-keys * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2)).ceil();
+        Bloom {
+            hashes: (bits / keys * ln2).round().max(1.) as u32,
+            bits: vec![0; (bits as usize).div_ceil(64)],
+        }
+    }
+
+    fn of<'a>(keys: impl ExactSizeIterator<Item = &'a String>) -> Bloom {
+        let mut res = Bloom::new(keys.len());
+        for k in keys {
+            res.insert(k);
+        }
+        res
+    }
+
+    // The bits that are set for `key`, derived from two hashes
+    fn positions(&self, key: &str) -> Vec<usize> {
+        let len = self.bits.len() as u64 * 64;
+        let h1 = fnv1a(key.as_bytes(), 0xcbf2_9ce4_8422_2325);
+        let h2 = fnv1a(key.as_bytes(), 0x8422_2325_cbf2_9ce4) | 1;
+        (0..self.hashes as u64)
+            .map(|i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+            .collect()
+    }
+
+    fn insert(&mut self, key: &str) {
+        for p in self.positions(key) {
+            self.bits[p / 64] |= 1 << (p % 64);
+        }
+    }
+
+    fn may_contain(&self, key: &str) -> bool {
+        self.positions(key)
+            .into_iter()
+            .all(|p| self.bits[p / 64] & (1 << (p % 64)) != 0)
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.hashes as u64).to_le_bytes());
+        out.extend_from_slice(&(self.bits.len() as u64).to_le_bytes());
+        for w in self.bits.iter() {
+            out.extend_from_slice(&w.to_le_bytes());
+        }
+    }
+
+    fn read(input: &mut &[u8]) -> anyhow::Result<Bloom> {
+        let hashes = read_u64(input)? as u32;
+        let len = read_u64(input)? as usize;
+        ensure!(
+            hashes > 0 && len > 0 && input.len() >= len * 8,
+            "invalid bloom filter"
+        );
+        let bits = (0..len)
+            .map(|_| read_u64(input))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Bloom { hashes, bits })
+    }
+}
+
+fn read_u64(input: &mut &[u8]) -> anyhow::Result<u64> {
+    ensure!(input.len() >= 8, "unexpected end of file");
+    let (n, rest) = input.split_at(8);
+    *input = rest;
+    Ok(u64::from_le_bytes(n.try_into().expect("split 8 bytes")))
+}
+
+fn write_time(out: &mut Vec<u8>, time: Option<NaiveDateTime>) {
+    let secs = time.map(|t| t.and_utc().timestamp()).unwrap_or(i64::MIN);
+    out.extend_from_slice(&secs.to_le_bytes());
+}
+
+fn read_time(input: &mut &[u8]) -> anyhow::Result<Option<NaiveDateTime>> {
+    let secs = read_u64(input)? as i64;
+    Ok(DateTime::from_timestamp(secs, 0).map(|t| t.naive_utc()))
+}
+
+// what can be found in a log file, to know without reading it whether it is
+// of interest
+pub struct Summary {
+    // the state of the file when it was summarized
+    stamp: Stamp,
+
+    // the times of its first and last postfix lines
+    pub first: Option<NaiveDateTime>,
+    pub last: Option<NaiveDateTime>,
+
+    // the message-id's, postfix id's and addresses it mentions
+    message_ids: Bloom,
+    queue_ids: Bloom,
+    senders: Bloom,
+    recipients: Bloom,
+}
+
+impl Summary {
+    // Where the summary of `file` is stored, as a hidden file next to it
+    fn path(file: &Path) -> PathBuf {
+        let mut name = std::ffi::OsString::from(".");
+        name.push(file.file_name().unwrap_or_default());
+        name.push(".mailparse-summary");
+        file.with_file_name(name)
+    }
+
+    // Summarize the fully-loaded `state` of a file, that was `stamp` before
+    // being loaded
+    pub fn build(stamp: Stamp, state: &State) -> Summary {
+        let mut message_ids = HashSet::new();
+        let mut queue_ids = HashSet::new();
+        let mut senders = HashSet::new();
+        let mut recipients = HashSet::new();
+        for line in state.lines.iter() {
+            if let ParsedLine::Postfix { id, details } =
+                ParsedLine::parse(format!("{}\n", line.text).as_bytes())
+            {
+                queue_ids.insert(id.id);
+                queue_ids.extend(details.previous_id);
+                queue_ids.extend(details.next_id);
+                message_ids.extend(details.message_id);
+                senders.extend(details.from.map(|f| f.to_lowercase()));
+                if let Some(d) = details.delivery {
+                    recipients.insert(d.to.to_lowercase());
+                    recipients.extend(d.orig_to.map(|o| o.to_lowercase()));
+                }
+            }
+        }
+        Summary {
+            stamp,
+            first: state.lines.iter().filter_map(|l| l.time).min(),
+            last: state.lines.iter().filter_map(|l| l.time).max(),
+            message_ids: Bloom::of(message_ids.iter()),
+            queue_ids: Bloom::of(queue_ids.iter()),
+            senders: Bloom::of(senders.iter()),
+            recipients: Bloom::of(recipients.iter()),
+        }
+    }
+
+    // Read the summary of `file`, if there is one that is up to date
+    //
+    // Only regular files have one, as what a stream holds changes every time
+    // it is read.
+    pub fn read(file: &Path) -> Option<Summary> {
+        if !input::is_regular(file) {
+            return None;
+        }
+        let stamp = Stamp::of(file).ok()?;
+        let data = std::fs::read(Summary::path(file)).ok()?;
+        let summary = Summary::parse(&data).ok()?;
+        if summary.stamp != stamp {
+            return None;
+        }
+        Some(summary)
+    }
+
+    fn parse(mut data: &[u8]) -> anyhow::Result<Summary> {
+        let input = &mut data;
+        ensure!(input.starts_with(MAGIC), "not a summary file");
+        *input = &input[MAGIC.len()..];
+        Ok(Summary {
+            stamp: Stamp {
+                size: read_u64(input)?,
+                mtime: read_u64(input)?,
+            },
+            first: read_time(input)?,
+            last: read_time(input)?,
+            message_ids: Bloom::read(input)?,
+            queue_ids: Bloom::read(input)?,
+            senders: Bloom::read(input)?,
+            recipients: Bloom::read(input)?,
+        })
+    }
+
+    // Store this as the summary of `file`
+    pub fn write(&self, file: &Path) -> anyhow::Result<()> {
+        let mut out = Vec::from(MAGIC);
+        out.extend_from_slice(&self.stamp.size.to_le_bytes());
+        out.extend_from_slice(&self.stamp.mtime.to_le_bytes());
+        write_time(&mut out, self.first);
+        write_time(&mut out, self.last);
+        for b in [
+            &self.message_ids,
+            &self.queue_ids,
+            &self.senders,
+            &self.recipients,
+        ]
+        .iter()
+        {
+            b.write(&mut out);
+        }
+
+        // Write to a temporary file first, so that concurrent runs never see
+        // a partial summary
+        let path = Summary::path(file);
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, out).with_context(|| format!("writing summary {:?}", tmp))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("writing summary {:?}", path))
+    }
+
+    pub fn may_have_message_id(&self, message_id: &str) -> bool {
+        self.message_ids.may_contain(message_id)
+    }
+
+    pub fn may_have_sender(&self, sender: &str) -> bool {
+        self.senders.may_contain(sender)
+    }
+
+    pub fn may_have_recipient(&self, recipient: &str) -> bool {
+        self.recipients.may_contain(recipient)
+    }
+
+    pub fn may_have_queue_id(&self, queue_id: &str) -> bool {
+        self.queue_ids.may_contain(queue_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("mailparse-summary-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("mail.log");
+        let lines = [
+            "2026-03-01T08:00:00+01:00 mx postfix/cleanup[2]: 4F3A2B1C9D: message-id=<a@example.org>",
+            "2026-03-01T08:00:01+01:00 mx postfix/qmgr[3]: 4F3A2B1C9D: from=<A@example.org>, size=1, nrcpt=1 (queue active)",
+            "2026-03-01T08:00:02+01:00 mx postfix/local[4]: 4F3A2B1C9D: to=<b@example.org>, relay=local, delay=2, delays=0/0/0/2, dsn=2.0.0, status=sent (delivered to mailbox)",
+        ];
+        std::fs::write(
+            &file,
+            lines.iter().map(|l| format!("{}\n", l)).collect::<String>(),
+        )
+        .unwrap();
+
+        let stamp = Stamp::of(&file).unwrap();
+        let mut state = State::new(0, file.clone());
+        for line in lines.iter() {
+            let _ = state.eat(format!("{}\n", line).as_bytes());
+        }
+        Summary::build(stamp, &state).write(&file).unwrap();
+
+        let summary = Summary::read(&file).unwrap();
+        let time = |s| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").unwrap();
+        assert_eq!(summary.first, Some(time("2026-03-01T08:00:00")));
+        assert_eq!(summary.last, Some(time("2026-03-01T08:00:02")));
+        assert!(summary.may_have_message_id("<a@example.org>"));
+        assert!(summary.may_have_queue_id("4F3A2B1C9D"));
+        assert!(summary.may_have_sender("a@example.org"));
+        assert!(summary.may_have_recipient("b@example.org"));
+        assert!(!summary.may_have_message_id("<b@example.org>"));
+        assert!(!summary.may_have_queue_id("5B6C7D8E9F"));
+        assert!(!summary.may_have_recipient("a@example.org"));
+
+        // A file that changed since must be read again
+        std::fs::write(&file, "").unwrap();
+        assert!(Summary::read(&file).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
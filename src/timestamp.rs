@@ -0,0 +1,196 @@
+use std::path::Path;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+
+const MONTHS: [&[u8]; 12] = [
+    b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec",
+];
+
+fn number(digits: &[u8]) -> Option<u32> {
+    let digits = match digits.iter().position(|&c| c != b' ') {
+        Some(first) => &digits[first..],
+        None => return None,
+    };
+    if digits.is_empty() || !digits.iter().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(digits.iter().fold(0, |n, &c| n * 10 + (c - b'0') as u32))
+}
+
+fn time(hms: &[u8]) -> Option<NaiveTime> {
+    if hms.len() != 8 || hms[2] != b':' || hms[5] != b':' {
+        return None;
+    }
+    NaiveTime::from_hms_opt(
+        number(&hms[0..2])?,
+        number(&hms[3..5])?,
+        number(&hms[6..8])?,
+    )
+}
+
+// Parse the timestamp at the beginning of a log line
+//
+// Traditional syslog timestamps (`Mar 24 10:00:00`) do not include the year,
+// so we pick the one that puts the line right before `reference`, usually the
+// modification time of the file. High-precision timestamps
+// (`2021-03-24T10:00:00.123456+01:00`) are taken in their local time, so that
+// they compare sensibly with traditional ones.
+pub fn parse(line: &[u8], reference: NaiveDateTime) -> Option<NaiveDateTime> {
+    if line.len() >= 19 && line[4] == b'-' && line[10] == b'T' {
+        let date = NaiveDate::from_ymd_opt(
+            number(&line[0..4])? as i32,
+            number(&line[5..7])?,
+            number(&line[8..10])?,
+        )?;
+        return Some(date.and_time(time(&line[11..19])?));
+    }
+
+    if line.len() < 15 || line[3] != b' ' || line[6] != b' ' {
+        return None;
+    }
+    let month = MONTHS.iter().position(|&m| m == &line[0..3])? as u32 + 1;
+    let day = number(&line[4..6])?;
+    let time = time(&line[7..15])?;
+    // Lines logged up to a day after the reference are most likely due to
+    // clock skew, anything later actually is from the previous year
+    let latest = reference + Duration::days(1);
+    [reference.year(), reference.year() - 1]
+        .iter()
+        .filter_map(|&y| NaiveDate::from_ymd_opt(y, month, day))
+        .map(|d| d.and_time(time))
+        .find(|&t| t <= latest)
+}
+
+// Strip the timestamp from the beginning of a log line, if any
+pub fn skip(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    if bytes.len() >= 19 && bytes[4] == b'-' && bytes[10] == b'T' {
+        return line.split_once(' ').map(|(_, rest)| rest).unwrap_or("");
+    }
+    if bytes.len() >= 16 && bytes[3] == b' ' && bytes[6] == b' ' && bytes[15] == b' ' {
+        return line.get(16..).unwrap_or(line);
+    }
+    line
+}
+
+// The time relative to which the year of syslog timestamps in `file` is guessed
+pub fn reference(file: &Path) -> NaiveDateTime {
+    std::fs::metadata(file)
+        .and_then(|m| m.modified())
+        .map(chrono::DateTime::<chrono::Local>::from)
+        .unwrap_or_else(|_| chrono::Local::now())
+        .naive_local()
+}
+
+// Find a date written as `2021-03-24` or `20210324` in a file name
+pub fn in_name(name: &str) -> Option<NaiveDate> {
+    let bytes = name.as_bytes();
+    let is_digit_at = |i: usize| bytes.get(i).map(|c| c.is_ascii_digit()).unwrap_or(false);
+    for start in 0..bytes.len() {
+        if (start > 0 && is_digit_at(start - 1)) || !is_digit_at(start) {
+            continue;
+        }
+        for (len, format) in [(10, "%Y-%m-%d"), (8, "%Y%m%d")].iter() {
+            let end = start + len;
+            if end > bytes.len() || is_digit_at(end) {
+                continue;
+            }
+            let candidate = match name.get(start..end) {
+                Some(c) => c,
+                None => continue,
+            };
+            if let Ok(d) = NaiveDate::parse_from_str(candidate, format) {
+                return Some(d);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, s)
+            .unwrap()
+    }
+
+    #[test]
+    fn syslog_year_from_reference() {
+        let reference = at(2021, 3, 24, 12, 0, 0);
+        assert_eq!(
+            parse(b"Mar 24 10:00:00 mx postfix/smtpd[1]: x", reference),
+            Some(at(2021, 3, 24, 10, 0, 0))
+        );
+        assert_eq!(
+            parse(b"Mar  1 00:00:00 mx postfix/smtpd[1]: x", reference),
+            Some(at(2021, 3, 1, 0, 0, 0))
+        );
+        // later in the year than the reference, so last year
+        assert_eq!(
+            parse(b"Apr  1 00:00:00 mx postfix/smtpd[1]: x", reference),
+            Some(at(2020, 4, 1, 0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn december_to_january() {
+        let reference = at(2026, 1, 2, 10, 0, 0);
+        assert_eq!(
+            parse(b"Dec 31 23:59:59 mx postfix/smtpd[1]: x", reference),
+            Some(at(2025, 12, 31, 23, 59, 59))
+        );
+        assert_eq!(
+            parse(b"Jan  1 00:00:00 mx postfix/smtpd[1]: x", reference),
+            Some(at(2026, 1, 1, 0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn clock_skew() {
+        let reference = at(2026, 1, 2, 10, 0, 0);
+        // up to a day after the reference
+        assert_eq!(
+            parse(b"Jan  3 09:00:00 mx postfix/smtpd[1]: x", reference),
+            Some(at(2026, 1, 3, 9, 0, 0))
+        );
+        assert_eq!(
+            parse(b"Jan  3 11:00:00 mx postfix/smtpd[1]: x", reference),
+            Some(at(2025, 1, 3, 11, 0, 0))
+        );
+    }
+
+    #[test]
+    fn leap_day() {
+        assert_eq!(
+            parse(
+                b"Feb 29 12:00:00 mx postfix/smtpd[1]: x",
+                at(2025, 3, 1, 0, 0, 0)
+            ),
+            Some(at(2024, 2, 29, 12, 0, 0))
+        );
+    }
+
+    #[test]
+    fn high_precision() {
+        assert_eq!(
+            parse(
+                b"2021-03-24T10:00:00.123456+01:00 mx postfix/smtpd[1]: x",
+                at(2026, 1, 1, 0, 0, 0)
+            ),
+            Some(at(2021, 3, 24, 10, 0, 0))
+        );
+    }
+
+    #[test]
+    fn not_a_timestamp() {
+        let reference = at(2026, 1, 1, 0, 0, 0);
+        assert_eq!(parse(b"Foo 24 10:00:00 mx", reference), None);
+        assert_eq!(parse(b"Mar 24 25:00:00 mx", reference), None);
+        assert_eq!(parse(b"Mar 32 10:00:00 mx", reference), None);
+        assert_eq!(parse(b"Mar 24", reference), None);
+    }
+}